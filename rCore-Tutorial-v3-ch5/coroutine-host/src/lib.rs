@@ -0,0 +1,97 @@
+//! The kernel's coroutine scheduling state machine, built for the host
+//!
+//! `os/src/task/coroutine.rs` is compiled here unchanged. It only reaches the
+//! rest of the kernel through `crate::config`, `crate::sync` and the
+//! [`SwitchBackend`] trait, so this crate provides the first two and
+//! [`MockContext`] stands in for the trap context.
+#![no_std]
+
+extern crate alloc;
+extern crate std;
+
+mod config {
+    /// same as `os/src/config.rs`
+    pub const PAGE_SIZE: usize = 0x1000;
+}
+
+#[path = "../../os/src/sync/up.rs"]
+mod sync;
+
+#[path = "../../os/src/task/coroutine.rs"]
+pub mod coroutine;
+
+use core::cell::Cell;
+pub use coroutine::SwitchBackend;
+
+std::thread_local! {
+    /// the clock of each test thread, advanced only by [`advance_clock`]
+    static CLOCK: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Advance the clock seen by [`MockContext::now_us`]
+pub fn advance_clock(us: usize) {
+    CLOCK.with(|clock| clock.set(clock.get() + us));
+}
+
+std::thread_local! {
+    /// state of the xorshift generator behind [`MockContext::sched_random`],
+    /// `None` for FIFO scheduling
+    static SCHED_RNG: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Turn deterministic scheduling on with `seed`, or off with `None`
+pub fn set_sched_seed(seed: Option<u64>) {
+    SCHED_RNG.with(|rng| rng.set(seed.map(|seed| (seed ^ 0x9e37_79b9_7f4a_7c15).max(1))));
+}
+
+/// Registers of a coroutine as the tests see them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MockContext {
+    /// general regs[0..31]
+    pub x: [usize; 32],
+    /// where the coroutine continues
+    pub pc: usize,
+}
+
+impl SwitchBackend for MockContext {
+    fn initial(entry: usize, stack_top: usize, arg: usize) -> Self {
+        let mut cx = Self {
+            pc: entry,
+            ..Self::default()
+        };
+        cx.x[2] = stack_top;
+        cx.x[10] = arg;
+        cx
+    }
+    fn load(&mut self, saved: &Self) {
+        *self = *saved;
+    }
+    fn arg(&self, index: usize) -> usize {
+        self.x[10 + index]
+    }
+    fn set_arg(&mut self, index: usize, value: usize) {
+        self.x[10 + index] = value;
+    }
+    fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+    fn set_sp(&mut self, sp: usize) {
+        self.x[2] = sp;
+    }
+    fn now_us() -> usize {
+        CLOCK.with(|clock| clock.get())
+    }
+    fn sched_random(bound: usize) -> Option<usize> {
+        SCHED_RNG.with(|rng| {
+            let mut state = rng.get()?;
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            rng.set(Some(state));
+            Some((state % bound.max(1) as u64) as usize)
+        })
+    }
+}
+
+/// Coroutine manager switching [`MockContext`]s
+pub type CoroutineManager = coroutine::CoroutineManager<MockContext>;
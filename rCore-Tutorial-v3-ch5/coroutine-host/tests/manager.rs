@@ -0,0 +1,646 @@
+//! Scenario tests for the coroutine manager, one kernel feature per test
+
+use coroutine_host::coroutine::{
+    CoroutineControlBlock, CoroutineSchedPage, CoroutineStatus, EXITED_RETAIN_LIMIT, FastPath,
+    ForkMode, MAIN_CID, MAX_STACK_SIZE, SCHED_READY_CAPACITY, SchedEntry, TraceKind, WaitReason,
+};
+use coroutine_host::{CoroutineManager, MockContext, SwitchBackend, advance_clock, set_sched_seed};
+use std::sync::Arc;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// The main flow as it was loaded from the ELF
+fn main_context() -> MockContext {
+    MockContext::initial(0x1_0000, 0x7000_0000, 0)
+}
+
+/// Create a coroutine whose stack may go anywhere in the address space
+fn create(
+    manager: &mut CoroutineManager,
+    entry: usize,
+    arg: usize,
+    stack_size: usize,
+) -> Option<(Arc<CoroutineControlBlock<MockContext>>, bool)> {
+    manager.create_coroutine(entry, arg, stack_size, |_, _| true)
+}
+
+fn current(manager: &CoroutineManager) -> usize {
+    manager.current_coroutine().unwrap().cid
+}
+
+fn status(manager: &CoroutineManager, cid: usize) -> CoroutineStatus {
+    manager
+        .get_coroutine(cid)
+        .unwrap()
+        .inner_exclusive_access()
+        .status
+}
+
+fn stack_top(manager: &CoroutineManager, cid: usize) -> usize {
+    let coroutine = manager.get_coroutine(cid).unwrap();
+    let inner = coroutine.inner_exclusive_access();
+    inner.stack_base + inner.stack_size
+}
+
+fn empty_sched_page() -> CoroutineSchedPage {
+    CoroutineSchedPage {
+        current_head: 0,
+        ready_len: 0,
+        valid: 0,
+        ready: [SchedEntry { cid: 0, user_cx: 0 }; SCHED_READY_CAPACITY],
+        fp_enabled: 0,
+    }
+}
+
+#[test]
+fn main_flow_is_running_coroutine_zero() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    assert_eq!(current(&manager), MAIN_CID);
+    assert_eq!(status(&manager, MAIN_CID), CoroutineStatus::Running);
+    assert!(!manager.has_ready_coroutine());
+    // 没有其他协程时让出什么也不做
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), None);
+    assert_eq!(cx, main_context());
+    // 主执行流不能以协程的方式退出
+    assert!(manager.exit_current_coroutine(0).is_none());
+    assert_eq!(status(&manager, MAIN_CID), CoroutineStatus::Running);
+}
+
+#[test]
+fn invalid_cids_are_rejected() {
+    let mut manager = CoroutineManager::new();
+    let (coroutine, _) = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    let missing = coroutine.cid + 1;
+    assert!(manager.get_coroutine(missing).is_none());
+    assert!(!manager.try_resume_coroutine(missing));
+    assert!(!manager.unblock_coroutine(missing));
+    assert!(!manager.detach_coroutine(missing));
+    assert_eq!(manager.notify_coroutine(missing, 1), -1);
+    // 正在运行的协程不能被恢复，主执行流不能被分离
+    assert!(!manager.try_resume_coroutine(MAIN_CID));
+    assert!(!manager.detach_coroutine(MAIN_CID));
+    // 就绪的协程不在阻塞队列里
+    assert!(!manager.unblock_coroutine(coroutine.cid));
+}
+
+#[test]
+fn yield_round_robins_and_preserves_registers() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 11, PAGE_SIZE).unwrap().0.cid;
+    let b = create(&mut manager, 0x200, 22, 3 * PAGE_SIZE)
+        .unwrap()
+        .0
+        .cid;
+
+    // 新协程从入口开始，在自己的栈顶上运行，a0 是参数
+    cx.x[8] = 0xaaaa;
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), Some(11));
+    assert_eq!(current(&manager), a);
+    assert_eq!(
+        (cx.pc, cx.x[2], cx.x[8]),
+        (0x100, stack_top(&manager, a), 0)
+    );
+    cx.x[8] = 0xbbbb;
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), Some(22));
+    assert_eq!((cx.pc, cx.x[2]), (0x200, stack_top(&manager, b)));
+    cx.pc = 0x240;
+
+    // 回到主执行流，它的寄存器原样恢复，让出得到0
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), Some(0));
+    assert_eq!(current(&manager), MAIN_CID);
+    assert_eq!((cx.pc, cx.x[8]), (0x1_0000, 0xaaaa));
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), Some(0));
+    assert_eq!(cx.x[8], 0xbbbb);
+    assert_eq!(manager.yield_current_coroutine(&mut cx, 0), Some(0));
+    assert_eq!(cx.pc, 0x240);
+
+    // 栈按页对齐，两个栈之间隔着一个保护页
+    let a_top = stack_top(&manager, a);
+    let b_base = stack_top(&manager, b) - 3 * PAGE_SIZE;
+    assert_eq!(b_base, a_top + PAGE_SIZE);
+}
+
+#[test]
+fn resume_wakes_and_runs_the_coroutine_next() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    let b = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+
+    // b 越过 a 先运行
+    assert!(manager.try_resume_coroutine(b));
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), b);
+
+    // b 挂起，下一个运行的是 a
+    manager.block_current_coroutine(WaitReason::Suspended);
+    assert_eq!(status(&manager, b), CoroutineStatus::Blocked);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), a);
+
+    // 恢复挂起的 b，它在主执行流之前运行
+    assert!(manager.try_resume_coroutine(b));
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), b);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+}
+
+#[test]
+fn notify_wakes_a_waiter_or_is_kept_for_later() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+
+    // a 不在等待，通知被保存，只能保存一个
+    assert_eq!(manager.notify_coroutine(a, 5), 1);
+    assert_eq!(manager.notify_coroutine(a, 6), -2);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(manager.take_pending_notify(), Some(5));
+    assert_eq!(manager.take_pending_notify(), None);
+
+    // a 等待通知，主执行流的通知唤醒它，它在 a0 中得到通知的值
+    manager.block_current_coroutine(WaitReason::Notify);
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(0));
+    assert_eq!(manager.notify_coroutine(a, 9), 0);
+    assert_eq!(status(&manager, a), CoroutineStatus::Ready);
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(9));
+    assert_eq!(cx.arg(0), 9);
+
+    // 已退出的协程不能再接收通知
+    manager.exit_current_coroutine(3).unwrap();
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(manager.notify_coroutine(a, 1), -1);
+    assert_eq!(manager.get_coroutine(a).unwrap().info().exit_code, 3);
+}
+
+#[test]
+fn invalid_stack_sizes_are_rejected() {
+    let mut manager = CoroutineManager::new();
+    for size in [0, 1, PAGE_SIZE + 1, MAX_STACK_SIZE + PAGE_SIZE, usize::MAX] {
+        assert!(create(&mut manager, 0x100, 0, size).is_none());
+    }
+    // 被拒绝的请求不占用协程ID，也不占用栈地址
+    let (first, _) = create(&mut manager, 0x100, 0, MAX_STACK_SIZE).unwrap();
+    let (second, _) = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    assert_eq!((first.cid, second.cid), (1, 2));
+    assert_eq!(
+        second.inner_exclusive_access().stack_base,
+        first.inner_exclusive_access().stack_base + MAX_STACK_SIZE + PAGE_SIZE
+    );
+}
+
+#[test]
+fn occupied_stack_ranges_are_rejected() {
+    let mut manager = CoroutineManager::new();
+    let (first, _) = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    let first_base = first.inner_exclusive_access().stack_base;
+    // 地址空间拒绝了新栈的范围，管理器保持不变
+    let mut checked = None;
+    let rejected = manager.create_coroutine(0x100, 0, 2 * PAGE_SIZE, |bottom, top| {
+        checked = Some((bottom, top));
+        false
+    });
+    assert!(rejected.is_none());
+    let bottom = first_base + 2 * PAGE_SIZE;
+    assert_eq!(checked, Some((bottom, bottom + 2 * PAGE_SIZE)));
+    let (second, _) = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    assert_eq!(second.cid, first.cid + 1);
+    assert_eq!(second.inner_exclusive_access().stack_base, bottom);
+}
+
+#[test]
+fn exited_control_blocks_are_reaped() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let rounds = 4 * EXITED_RETAIN_LIMIT;
+    for round in 0..rounds {
+        let cid = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+        manager.switch_to_next_coroutine(&mut cx, 0);
+        let exited = manager.exit_current_coroutine(round as i32).unwrap();
+        assert_eq!(exited.cid, cid);
+        manager.cache_stack(&exited);
+        manager.switch_to_next_coroutine(&mut cx, 0);
+        assert_eq!(current(&manager), MAIN_CID);
+        assert!(manager.coroutine_infos().len() <= EXITED_RETAIN_LIMIT + 1);
+    }
+    // 只保留最近退出的协程，它们的退出码仍可查询
+    let infos = manager.coroutine_infos();
+    assert_eq!(infos.len(), EXITED_RETAIN_LIMIT + 1);
+    let last = infos.last().unwrap();
+    assert_eq!(last.exit_code, rounds as isize - 1);
+    assert!(manager.get_coroutine(1).is_none());
+
+    // 分离的已退出协程立即被回收，不占用保留的名额
+    let kept = infos[1].cid;
+    assert!(manager.detach_coroutine(kept));
+    assert!(manager.get_coroutine(kept).is_none());
+    assert_eq!(manager.coroutine_infos().len(), EXITED_RETAIN_LIMIT);
+}
+
+#[test]
+fn exited_stacks_are_cached_and_reused_best_fit() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let small = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0;
+    let large = create(&mut manager, 0x100, 0, 4 * PAGE_SIZE).unwrap().0;
+    for _ in 0..2 {
+        manager.switch_to_next_coroutine(&mut cx, 0);
+        let exited = manager.exit_current_coroutine(0).unwrap();
+        assert!(manager.cache_stack(&exited));
+    }
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+
+    // 两页的请求放不进一页的栈，取能容纳它的最小的栈
+    let (coroutine, cached) = create(&mut manager, 0x100, 0, 2 * PAGE_SIZE).unwrap();
+    assert!(cached);
+    let inner = coroutine.inner_exclusive_access();
+    let large_inner = large.inner_exclusive_access();
+    assert_eq!(
+        (inner.stack_base, inner.stack_size),
+        (large_inner.stack_base, 4 * PAGE_SIZE)
+    );
+    drop((inner, large_inner));
+    let (coroutine, cached) = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    assert!(cached);
+    assert_eq!(
+        coroutine.inner_exclusive_access().stack_base,
+        small.inner_exclusive_access().stack_base
+    );
+    assert!(!create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().1);
+
+    // 缓存满了以后调用者自己回收栈
+    let (_, dropped) = manager.set_stack_cache_limit(0);
+    assert!(dropped.is_empty());
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    let exited = manager.exit_current_coroutine(0).unwrap();
+    assert!(!manager.cache_stack(&exited));
+}
+
+#[test]
+fn detached_coroutines_are_reaped_after_exit() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    let b = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    assert!(manager.detach_coroutine(a));
+
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.exit_current_coroutine(0).unwrap();
+    // 退出的协程还是当前协程，切换出去以后才被回收
+    assert!(manager.get_coroutine(a).is_some());
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert!(manager.get_coroutine(a).is_none());
+
+    // 已经退出的协程在分离时立即被回收
+    manager.exit_current_coroutine(0).unwrap();
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(status(&manager, b), CoroutineStatus::Exited);
+    assert!(manager.detach_coroutine(b));
+    assert!(manager.get_coroutine(b).is_none());
+    let cids: Vec<usize> = manager
+        .coroutine_infos()
+        .iter()
+        .map(|info| info.cid)
+        .collect();
+    assert_eq!(cids, [MAIN_CID]);
+}
+
+#[test]
+fn fork_copies_or_drops_coroutines() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let waiter = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    let ready = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.block_current_coroutine(WaitReason::Child(-1));
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+
+    // 子进程没有子进程，等待子进程的协程在子进程中被唤醒，排在就绪队列最后
+    let (mut child, dropped) = manager.fork(ForkMode::AllCoroutines);
+    assert!(dropped.is_empty());
+    assert_eq!(status(&manager, waiter), CoroutineStatus::Blocked);
+    assert_eq!(status(&child, waiter), CoroutineStatus::Ready);
+    assert_eq!(current(&child), MAIN_CID);
+    let mut child_cx = cx;
+    child.switch_to_next_coroutine(&mut child_cx, 0);
+    assert_eq!(current(&child), ready);
+    child.switch_to_next_coroutine(&mut child_cx, 0);
+    assert_eq!(current(&child), waiter);
+    child.switch_to_next_coroutine(&mut child_cx, 0);
+    assert_eq!(current(&child), MAIN_CID);
+    // 父进程的协程不受影响
+    assert_eq!(current(&manager), MAIN_CID);
+
+    // 只保留调用者时，其余未退出协程的栈交给调用者回收
+    let (child, mut dropped) = manager.fork(ForkMode::CurrentCoroutine);
+    dropped.sort_unstable();
+    let mut expected = [waiter, ready].map(|cid| {
+        manager
+            .get_coroutine(cid)
+            .unwrap()
+            .inner_exclusive_access()
+            .stack_base
+    });
+    expected.sort_unstable();
+    assert_eq!(dropped, expected);
+    let cids: Vec<usize> = child
+        .coroutine_infos()
+        .iter()
+        .map(|info| info.cid)
+        .collect();
+    assert_eq!(cids, [MAIN_CID]);
+}
+
+#[test]
+fn deadlock_is_detected_only_without_possible_wakeups() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    assert!(manager.find_deadlock(|_| false).is_none());
+
+    manager.block_current_coroutine(WaitReason::Suspended);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.block_current_coroutine(WaitReason::Child(7));
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), None);
+
+    // 子进程7还在时它退出会唤醒 a
+    assert!(manager.find_deadlock(|pid| pid == 7).is_none());
+    let waits = manager.find_deadlock(|_| false).unwrap();
+    let waits: Vec<(usize, WaitReason)> = waits.iter().map(|(c, r)| (c.cid, *r)).collect();
+    assert_eq!(
+        waits,
+        [(MAIN_CID, WaitReason::Suspended), (a, WaitReason::Child(7))]
+    );
+
+    // 主执行流的阻塞系统调用得到错误码
+    assert!(manager.interrupt_main_wait(-4));
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(-4));
+    assert_eq!(current(&manager), MAIN_CID);
+}
+
+#[test]
+fn notify_waiters_deadlock_without_children() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    let b = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+
+    // 所有协程都在等待通知，进程内没有谁能通知它们
+    manager.block_current_coroutine(WaitReason::Notify);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.block_current_coroutine(WaitReason::Notify);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), b);
+    assert!(manager.find_deadlock(|_| false).is_none());
+    manager.block_current_coroutine(WaitReason::Notify);
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), None);
+    let waits = manager.find_deadlock(|_| false).unwrap();
+    let waits: Vec<(usize, WaitReason)> = waits.iter().map(|(c, r)| (c.cid, *r)).collect();
+    assert_eq!(
+        waits,
+        [
+            (MAIN_CID, WaitReason::Notify),
+            (a, WaitReason::Notify),
+            (b, WaitReason::Notify)
+        ]
+    );
+    // 子进程还可能发来通知
+    assert!(manager.find_deadlock(|pid| pid == -1).is_none());
+
+    // 通知到来后不再死锁
+    assert_eq!(manager.notify_coroutine(a, 5), 0);
+    assert!(manager.find_deadlock(|_| false).is_none());
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(5));
+    assert_eq!(current(&manager), a);
+}
+
+#[test]
+fn sched_page_round_trip() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let mut page = empty_sched_page();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    let b = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+
+    // 登记之前不发布
+    manager.publish_to_sched_page(&mut page);
+    assert_eq!(page.valid, 0);
+    let fast_path = FastPath {
+        restore_entry: 0x300,
+        begin: 0x400,
+        end: 0x440,
+    };
+    manager.enable_fast_path(fast_path);
+    assert_eq!(manager.restart_fast_switch(0x420), 0x400);
+    assert_eq!(manager.restart_fast_switch(0x440), 0x440);
+
+    manager.publish_to_sched_page(&mut page);
+    assert_eq!(
+        (page.valid, page.ready_len, page.current_head),
+        (1, 2, MAIN_CID << 8)
+    );
+    assert_eq!([page.ready[0].cid, page.ready[1].cid], [a, b]);
+
+    // 用户态从主执行流切换到 a：取出队首，主执行流带着保存寄存器的地址排到队尾
+    page.ready[2] = SchedEntry {
+        cid: MAIN_CID,
+        user_cx: 0x9000,
+    };
+    page.current_head = (a << 8) | 1;
+    manager.sync_from_sched_page(&page);
+    assert_eq!(current(&manager), a);
+    assert_eq!(status(&manager, MAIN_CID), CoroutineStatus::Ready);
+    let main = manager.get_coroutine(MAIN_CID).unwrap();
+    assert_eq!(main.inner_exclusive_access().user_cx, Some(0x9000));
+    assert_eq!(manager.get_coroutine(a).unwrap().info().switch_count, 1);
+
+    // 经内核切换回主执行流时由用户态的恢复例程装入它的寄存器
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), b);
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(0x9000));
+    assert_eq!(cx.pc, 0x300);
+
+    // 协程集合对不上的页被忽略
+    manager.publish_to_sched_page(&mut page);
+    page.ready[0].cid = 99;
+    page.current_head = (99 << 8) | 1;
+    manager.sync_from_sched_page(&page);
+    assert_eq!(current(&manager), MAIN_CID);
+}
+
+#[test]
+fn upcalls_report_blocking_and_waking() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let handler = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0;
+    manager.install_upcall(&handler, 0x5000);
+    let worker = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+
+    // 处理函数空闲时不参与调度
+    assert_eq!(status(&manager, handler.cid), CoroutineStatus::Blocked);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), worker);
+
+    // worker 阻塞后处理函数下一个运行，从入口开始处理事件
+    manager.block_current_coroutine(WaitReason::Notify);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), handler.cid);
+    assert_eq!((cx.pc, cx.x[2]), (0x5000, stack_top(&manager, handler.cid)));
+    assert_eq!((cx.arg(0), cx.arg(1)), (0, worker));
+
+    // 处理函数运行时产生的事件在它返回时交给它
+    assert_eq!(manager.notify_coroutine(worker, 7), 0);
+    cx.pc = 0x5080;
+    assert_eq!(manager.upcall_return(&mut cx), Some(true));
+    assert_eq!((cx.pc, cx.arg(0), cx.arg(1)), (0x5000, 1, worker));
+    assert_eq!(manager.upcall_return(&mut cx), Some(false));
+    assert_eq!(status(&manager, handler.cid), CoroutineStatus::Blocked);
+    assert!(manager.find_deadlock(|_| false).is_none());
+
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+    assert_eq!(manager.upcall_return(&mut cx), None);
+    assert_eq!(manager.switch_to_next_coroutine(&mut cx, 0), Some(7));
+    assert_eq!(current(&manager), worker);
+
+    // 暂停后不再有事件
+    assert_eq!(manager.set_upcall_entry(0), Some(handler.cid));
+    manager.block_current_coroutine(WaitReason::Suspended);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+    assert!(!manager.has_ready_coroutine());
+}
+
+#[test]
+fn stats_follow_the_clock() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let a = create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap().0.cid;
+    advance_clock(100);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    advance_clock(50);
+    manager.block_current_coroutine(WaitReason::Suspended);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    advance_clock(30);
+    let stats = manager.get_coroutine(a).unwrap().stats();
+    assert_eq!((stats.run_time_us, stats.blocked_time_us), (50, 30));
+    assert!(manager.unblock_coroutine(a));
+    advance_clock(20);
+
+    // 任务被换下CPU的时间不算在当前协程上
+    manager.pause_current_coroutine();
+    advance_clock(1000);
+    manager.continue_current_coroutine();
+    manager.preempt_current_coroutine();
+    let main = manager.get_coroutine(MAIN_CID).unwrap().stats();
+    assert_eq!(main.run_time_us, 100 + 30 + 20);
+    assert_eq!((main.schedule_count, main.voluntary_switches), (2, 1));
+    assert_eq!(main.involuntary_switches, 1);
+    let stats = manager.get_coroutine(a).unwrap().stats();
+    assert_eq!((stats.blocked_time_us, stats.schedule_count), (30, 1));
+}
+
+#[test]
+fn trace_records_follow_the_switches() {
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    assert!(!manager.set_trace(Some(7)));
+    let a = create(&mut manager, 0x200, 0, PAGE_SIZE).unwrap().0.cid;
+    assert!(manager.try_resume_coroutine(a));
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    advance_clock(10);
+    manager.block_current_coroutine(WaitReason::Suspended);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert!(manager.try_resume_coroutine(a));
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    manager.exit_current_coroutine(3);
+    manager.switch_to_next_coroutine(&mut cx, 0);
+    assert_eq!(current(&manager), MAIN_CID);
+    // 关闭跟踪以后不再记录
+    assert!(manager.set_trace(None));
+    create(&mut manager, 0x100, 0, PAGE_SIZE).unwrap();
+    manager.switch_to_next_coroutine(&mut cx, 0);
+
+    let records = manager.take_trace_records();
+    let events: Vec<_> = records.iter().map(|r| (r.cid, r.kind, r.arg)).collect();
+    let expected = [
+        (a, TraceKind::Create, MAIN_CID),
+        (MAIN_CID, TraceKind::SwitchOut, 0),
+        (a, TraceKind::SwitchIn, 0),
+        (a, TraceKind::Block, 0),
+        (a, TraceKind::SwitchOut, 0),
+        (MAIN_CID, TraceKind::SwitchIn, 0),
+        (a, TraceKind::Unblock, 0),
+        (MAIN_CID, TraceKind::SwitchOut, 0),
+        (a, TraceKind::SwitchIn, 0),
+        (a, TraceKind::Exit, 3),
+        (a, TraceKind::SwitchOut, 0),
+        (MAIN_CID, TraceKind::SwitchIn, 0),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|&(cid, kind, arg)| (cid, kind as usize, arg))
+        .collect();
+    assert_eq!(events, expected);
+    assert!(records.iter().all(|r| r.pid == 7));
+    assert!(records.windows(2).all(|w| w[0].time_us <= w[1].time_us));
+    assert!(records[3].time_us >= records[2].time_us + 10);
+    assert!(manager.take_trace_records().is_empty());
+}
+
+/// Order in which the coroutines run when they all keep yielding
+fn yield_order(seed: Option<u64>) -> Vec<usize> {
+    set_sched_seed(seed);
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    for entry in 1..=4 {
+        create(&mut manager, entry * 0x100, 0, PAGE_SIZE).unwrap();
+    }
+    let order = (0..40)
+        .map(|_| {
+            manager.yield_current_coroutine(&mut cx, 0);
+            current(&manager)
+        })
+        .collect();
+    set_sched_seed(None);
+    order
+}
+
+#[test]
+fn seeded_scheduling_is_reproducible() {
+    let fifo: Vec<usize> = (0..40).map(|i| (i + 1) % 5).collect();
+    assert_eq!(yield_order(None), fifo);
+    // 同一个种子总是得到相同的顺序，不同的种子得到不同的顺序
+    assert_eq!(yield_order(Some(42)), yield_order(Some(42)));
+    assert_ne!(yield_order(Some(42)), fifo);
+    assert_ne!(yield_order(Some(42)), yield_order(Some(43)));
+
+    // 被恢复的协程仍然下一个运行
+    set_sched_seed(Some(7));
+    let mut manager = CoroutineManager::new();
+    let mut cx = main_context();
+    let cids: Vec<usize> = (1..=4)
+        .map(|entry| {
+            create(&mut manager, entry * 0x100, 0, PAGE_SIZE)
+                .unwrap()
+                .0
+                .cid
+        })
+        .collect();
+    for _ in 0..10 {
+        for &cid in cids.iter().rev() {
+            if current(&manager) != cid {
+                assert!(manager.try_resume_coroutine(cid));
+                manager.yield_current_coroutine(&mut cx, 0);
+                assert_eq!(current(&manager), cid);
+            }
+        }
+    }
+    set_sched_seed(None);
+}
@@ -0,0 +1,534 @@
+//! Randomized tests: drive the coroutine manager with random operations and
+//! compare every result with a simple reference model
+//!
+//! Each seed is replayed deterministically, so a failure message naming the
+//! seed and step is enough to reproduce it.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use coroutine_host::coroutine::{CoroutineStatus, EXITED_RETAIN_LIMIT, MAIN_CID, WaitReason};
+use coroutine_host::{CoroutineManager, MockContext, SwitchBackend, advance_clock};
+
+const SEEDS: u64 = 300;
+const STEPS: usize = 400;
+const PAGE_SIZE: usize = 0x1000;
+const STACK_CACHE_LIMIT: usize = 8;
+
+/// xorshift64*, enough to pick operations
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as usize
+    }
+    fn below(&mut self, n: usize) -> usize {
+        self.next() % n
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Create {
+        entry: usize,
+        arg: usize,
+        stack_size: usize,
+    },
+    Yield,
+    Resume(usize),
+    Suspend,
+    WaitNotify,
+    Exit(i32),
+    Unblock(usize),
+    Notify(usize, usize),
+    Detach(usize),
+    Tick(usize),
+}
+
+/// What the model expects of one coroutine
+struct Expected {
+    status: CoroutineStatus,
+    reason: Option<WaitReason>,
+    pending_notify: Option<usize>,
+    wake_ret: Option<usize>,
+    /// registers it gets when switched in
+    saved: MockContext,
+    switch_count: usize,
+    detached: bool,
+    stack: (usize, usize),
+}
+
+struct Model {
+    coroutines: BTreeMap<usize, Expected>,
+    current: usize,
+    ready: VecDeque<usize>,
+    next_cid: usize,
+    /// exited coroutines that are not detached, in the order they exited
+    exited: VecDeque<usize>,
+    /// sizes of the cached stacks
+    stack_cache: Vec<usize>,
+    /// the current coroutine blocked or exited with nothing ready, so the task
+    /// waits until another process wakes one of its coroutines
+    stalled: bool,
+}
+
+impl Model {
+    fn new(main_cx: MockContext) -> Self {
+        let mut coroutines = BTreeMap::new();
+        coroutines.insert(
+            MAIN_CID,
+            Expected {
+                status: CoroutineStatus::Running,
+                reason: None,
+                pending_notify: None,
+                wake_ret: None,
+                saved: main_cx,
+                switch_count: 1,
+                detached: false,
+                stack: (0, 0),
+            },
+        );
+        Self {
+            coroutines,
+            current: MAIN_CID,
+            ready: VecDeque::new(),
+            next_cid: MAIN_CID + 1,
+            exited: VecDeque::new(),
+            stack_cache: Vec::new(),
+            stalled: false,
+        }
+    }
+
+    fn status(&self, cid: usize) -> Option<CoroutineStatus> {
+        self.coroutines.get(&cid).map(|coroutine| coroutine.status)
+    }
+
+    fn block_current(&mut self, reason: WaitReason) {
+        let current = self.coroutines.get_mut(&self.current).unwrap();
+        current.status = CoroutineStatus::Blocked;
+        current.reason = Some(reason);
+    }
+
+    fn unblock(&mut self, cid: usize) -> bool {
+        if self.status(cid) != Some(CoroutineStatus::Blocked) {
+            return false;
+        }
+        let coroutine = self.coroutines.get_mut(&cid).unwrap();
+        coroutine.status = CoroutineStatus::Ready;
+        coroutine.reason = None;
+        self.ready.push_back(cid);
+        true
+    }
+
+    /// Switch to the next ready coroutine, returning the a0 it resumes with
+    fn switch(&mut self, cx: &MockContext, ret: usize) -> Option<usize> {
+        if self.ready.is_empty() {
+            self.stalled = self.status(self.current) != Some(CoroutineStatus::Running);
+            return None;
+        }
+        let current = self.coroutines.get_mut(&self.current).unwrap();
+        current.saved = *cx;
+        current.saved.set_arg(0, ret);
+        if current.status == CoroutineStatus::Running {
+            current.status = CoroutineStatus::Ready;
+            self.ready.push_back(self.current);
+        }
+        let next_cid = self.ready.pop_front().unwrap();
+        let next = self.coroutines.get_mut(&next_cid).unwrap();
+        next.status = CoroutineStatus::Running;
+        next.switch_count += 1;
+        if let Some(wake_ret) = next.wake_ret.take() {
+            next.saved.set_arg(0, wake_ret);
+        }
+        let next_ret = next.saved.arg(0);
+        self.current = next_cid;
+        self.stalled = false;
+        self.reap();
+        Some(next_ret)
+    }
+
+    fn reap(&mut self) {
+        let current = self.current;
+        let excess = self.exited.len().saturating_sub(EXITED_RETAIN_LIMIT);
+        let reaped: Vec<usize> = self.exited.drain(..excess).collect();
+        self.coroutines.retain(|cid, coroutine| {
+            *cid == current
+                || coroutine.status != CoroutineStatus::Exited
+                || !(coroutine.detached || reaped.contains(cid))
+        });
+    }
+}
+
+struct Harness {
+    seed: u64,
+    /// stop creating coroutines once this many exist
+    max_coroutines: usize,
+    step: usize,
+    rng: Rng,
+    manager: CoroutineManager,
+    model: Model,
+    cx: MockContext,
+}
+
+impl Harness {
+    fn new(seed: u64, max_coroutines: usize) -> Self {
+        let cx = MockContext::initial(0x1_0000, 0x7000_0000, 0);
+        Self {
+            seed,
+            max_coroutines,
+            step: 0,
+            rng: Rng::new(seed),
+            manager: CoroutineManager::new(),
+            model: Model::new(cx),
+            cx,
+        }
+    }
+
+    fn at(&self, op: Op) -> String {
+        format!("seed {} step {} {:?}", self.seed, self.step, op)
+    }
+
+    /// A cid that is usually valid, sometimes never allocated
+    fn any_cid(&mut self) -> usize {
+        self.rng.below(self.model.next_cid + 2)
+    }
+
+    fn random_op(&mut self) -> Op {
+        // 停住的任务里没有协程在运行，只能等待其他进程唤醒它的协程
+        if self.model.stalled {
+            return match self.rng.below(3) {
+                0 => Op::Unblock(self.any_cid()),
+                1 => Op::Notify(self.any_cid(), self.rng.below(1000)),
+                _ => Op::Tick(self.rng.below(100)),
+            };
+        }
+        match self.rng.below(20) {
+            0..=3 if self.model.coroutines.len() < self.max_coroutines => Op::Create {
+                entry: 0x100 * (1 + self.rng.below(64)),
+                arg: self.rng.below(1000),
+                // 偶尔请求不合法的栈大小
+                stack_size: match self.rng.below(8) {
+                    0 => self.rng.below(3 * PAGE_SIZE),
+                    _ => PAGE_SIZE * (1 + self.rng.below(3)),
+                },
+            },
+            0..=6 => Op::Yield,
+            7..=8 => Op::Resume(self.any_cid()),
+            9 => Op::Suspend,
+            10 => Op::WaitNotify,
+            11..=12 => Op::Exit(self.rng.below(100) as i32),
+            13..=14 => Op::Unblock(self.any_cid()),
+            15..=16 => Op::Notify(self.any_cid(), self.rng.below(1000)),
+            17 => Op::Detach(self.any_cid()),
+            _ => Op::Tick(self.rng.below(100)),
+        }
+    }
+
+    /// Switch in both the manager and the model and compare the registers
+    fn switch(&mut self, op: Op, ret: usize) {
+        let expected = self.model.switch(&self.cx, ret);
+        let actual = self
+            .manager
+            .switch_to_next_coroutine(&mut self.cx, ret as isize);
+        assert_eq!(actual, expected.map(|ret| ret as isize), "{}", self.at(op));
+        if expected.is_some() {
+            let saved = self.model.coroutines[&self.model.current].saved;
+            assert_eq!(self.cx, saved, "{}: registers", self.at(op));
+        }
+    }
+
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Create {
+                entry,
+                arg,
+                stack_size,
+            } => {
+                let created = self
+                    .manager
+                    .create_coroutine(entry, arg, stack_size, |_, _| true);
+                if stack_size == 0 || stack_size % PAGE_SIZE != 0 {
+                    assert!(created.is_none(), "{}", self.at(op));
+                    return;
+                }
+                let best_fit = self
+                    .model
+                    .stack_cache
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cached)| **cached >= stack_size)
+                    .min_by_key(|(_, cached)| **cached)
+                    .map(|(index, _)| index);
+                let (coroutine, cached) = created.unwrap_or_else(|| panic!("{}", self.at(op)));
+                assert_eq!(coroutine.cid, self.model.next_cid, "{}", self.at(op));
+                assert_eq!(cached, best_fit.is_some(), "{}", self.at(op));
+                let expected_size = match best_fit {
+                    Some(index) => self.model.stack_cache.swap_remove(index),
+                    None => stack_size,
+                };
+                let inner = coroutine.inner_exclusive_access();
+                assert_eq!(inner.stack_size, expected_size, "{}", self.at(op));
+                assert_eq!(inner.stack_base % PAGE_SIZE, 0, "{}", self.at(op));
+                let stack = (inner.stack_base, inner.stack_size);
+                drop(inner);
+                self.model.coroutines.insert(
+                    coroutine.cid,
+                    Expected {
+                        status: CoroutineStatus::Ready,
+                        reason: None,
+                        pending_notify: None,
+                        wake_ret: None,
+                        saved: MockContext::initial(entry, stack.0 + stack.1, arg),
+                        switch_count: 0,
+                        detached: false,
+                        stack,
+                    },
+                );
+                self.model.ready.push_back(coroutine.cid);
+                self.model.next_cid += 1;
+            }
+            Op::Yield => {
+                let expected = self.model.switch(&self.cx, 0);
+                let actual = self.manager.yield_current_coroutine(&mut self.cx, 0);
+                assert_eq!(actual, expected.map(|ret| ret as isize), "{}", self.at(op));
+                let saved = self.model.coroutines[&self.model.current].saved;
+                if expected.is_some() {
+                    assert_eq!(self.cx, saved, "{}: registers", self.at(op));
+                }
+            }
+            Op::Resume(cid) => {
+                let expected = cid != self.model.current
+                    && matches!(
+                        self.model.status(cid),
+                        Some(CoroutineStatus::Ready | CoroutineStatus::Blocked)
+                    );
+                assert_eq!(
+                    self.manager.try_resume_coroutine(cid),
+                    expected,
+                    "{}",
+                    self.at(op)
+                );
+                if expected {
+                    self.model.unblock(cid);
+                    let index = self
+                        .model
+                        .ready
+                        .iter()
+                        .position(|ready| *ready == cid)
+                        .unwrap();
+                    self.model.ready.remove(index);
+                    self.model.ready.push_front(cid);
+                    self.switch(op, 0);
+                }
+            }
+            Op::Suspend => {
+                self.manager.block_current_coroutine(WaitReason::Suspended);
+                self.model.block_current(WaitReason::Suspended);
+                self.switch(op, 0);
+            }
+            Op::WaitNotify => {
+                let pending = self
+                    .model
+                    .coroutines
+                    .get_mut(&self.model.current)
+                    .unwrap()
+                    .pending_notify
+                    .take();
+                assert_eq!(
+                    self.manager.take_pending_notify(),
+                    pending,
+                    "{}",
+                    self.at(op)
+                );
+                if pending.is_none() {
+                    self.manager.block_current_coroutine(WaitReason::Notify);
+                    self.model.block_current(WaitReason::Notify);
+                    self.switch(op, 0);
+                }
+            }
+            Op::Exit(code) => {
+                let exited = self.manager.exit_current_coroutine(code);
+                if self.model.current == MAIN_CID {
+                    assert!(exited.is_none(), "{}", self.at(op));
+                    return;
+                }
+                let exited = exited.unwrap_or_else(|| panic!("{}", self.at(op)));
+                assert_eq!(exited.cid, self.model.current, "{}", self.at(op));
+                let current = self.model.coroutines.get_mut(&self.model.current).unwrap();
+                current.status = CoroutineStatus::Exited;
+                if !current.detached {
+                    self.model.exited.push_back(self.model.current);
+                }
+                let size = current.stack.1;
+                let cache = self.model.stack_cache.len() < STACK_CACHE_LIMIT;
+                assert_eq!(self.manager.cache_stack(&exited), cache, "{}", self.at(op));
+                if cache {
+                    self.model.stack_cache.push(size);
+                }
+                self.switch(op, 0);
+            }
+            Op::Unblock(cid) => {
+                let expected = self.model.unblock(cid);
+                assert_eq!(
+                    self.manager.unblock_coroutine(cid),
+                    expected,
+                    "{}",
+                    self.at(op)
+                );
+            }
+            Op::Notify(cid, value) => {
+                let expected = match self.model.coroutines.get_mut(&cid) {
+                    None => -1,
+                    Some(coroutine) if coroutine.status == CoroutineStatus::Exited => -1,
+                    Some(coroutine) if coroutine.reason == Some(WaitReason::Notify) => {
+                        coroutine.wake_ret = Some(value);
+                        self.model.unblock(cid);
+                        0
+                    }
+                    Some(coroutine) if coroutine.pending_notify.is_some() => -2,
+                    Some(coroutine) => {
+                        coroutine.pending_notify = Some(value);
+                        1
+                    }
+                };
+                assert_eq!(
+                    self.manager.notify_coroutine(cid, value),
+                    expected,
+                    "{}",
+                    self.at(op)
+                );
+            }
+            Op::Detach(cid) => {
+                let expected = cid != MAIN_CID && self.model.coroutines.contains_key(&cid);
+                assert_eq!(
+                    self.manager.detach_coroutine(cid),
+                    expected,
+                    "{}",
+                    self.at(op)
+                );
+                if expected {
+                    self.model.coroutines.get_mut(&cid).unwrap().detached = true;
+                    self.model.exited.retain(|&exited| exited != cid);
+                    self.model.reap();
+                }
+            }
+            Op::Tick(us) => advance_clock(us),
+        }
+        // 停住的任务重新被调度时再试着切换
+        if self.model.stalled && !matches!(op, Op::Suspend | Op::WaitNotify | Op::Exit(_)) {
+            self.switch(op, 0);
+        }
+    }
+
+    fn check_invariants(&self, op: Op) {
+        let at = self.at(op);
+        let manager = &self.manager;
+        let model = &self.model;
+        assert_eq!(
+            manager.current_coroutine().unwrap().cid,
+            model.current,
+            "{}",
+            at
+        );
+        assert_eq!(
+            manager.has_ready_coroutine(),
+            !model.ready.is_empty(),
+            "{}",
+            at
+        );
+
+        // 按创建顺序排列的协程与模型一致
+        let infos = manager.coroutine_infos();
+        let cids: Vec<usize> = infos.iter().map(|info| info.cid).collect();
+        let expected_cids: Vec<usize> = model.coroutines.keys().copied().collect();
+        assert_eq!(cids, expected_cids, "{}", at);
+        for info in infos.iter() {
+            let expected = &model.coroutines[&info.cid];
+            assert_eq!(
+                info.status, expected.status as usize,
+                "{}: cid {}",
+                at, info.cid
+            );
+            assert_eq!(
+                info.switch_count, expected.switch_count,
+                "{}: cid {}",
+                at, info.cid
+            );
+        }
+        let running = infos
+            .iter()
+            .filter(|info| info.status == CoroutineStatus::Running as usize)
+            .count();
+        assert_eq!(running, if model.stalled { 0 } else { 1 }, "{}", at);
+
+        // 未退出协程的栈互不重叠
+        let mut stacks: Vec<(usize, usize)> = model
+            .coroutines
+            .values()
+            .filter(|coroutine| {
+                coroutine.status != CoroutineStatus::Exited && coroutine.stack.1 > 0
+            })
+            .map(|coroutine| coroutine.stack)
+            .collect();
+        stacks.sort_unstable();
+        for pair in stacks.windows(2) {
+            assert!(pair[0].0 + pair[0].1 <= pair[1].0, "{}: stacks overlap", at);
+        }
+
+        // 没有子进程时，只有等待显式恢复或通知的协程就算死锁
+        let deadlocked = model.ready.is_empty()
+            && model
+                .coroutines
+                .values()
+                .all(|coroutine| match coroutine.status {
+                    CoroutineStatus::Exited => true,
+                    CoroutineStatus::Blocked => matches!(
+                        coroutine.reason,
+                        Some(WaitReason::Suspended | WaitReason::Notify)
+                    ),
+                    _ => false,
+                });
+        assert_eq!(
+            manager.find_deadlock(|_| false).is_some(),
+            deadlocked,
+            "{}",
+            at
+        );
+        assert!(manager.get_coroutine(model.next_cid).is_none(), "{}", at);
+    }
+
+    fn run(&mut self) {
+        for step in 0..STEPS {
+            self.step = step;
+            // 当前协程在两次系统调用之间改动了自己的寄存器
+            if !self.model.stalled {
+                self.cx.pc = self.rng.next();
+                self.cx.x[8] = self.rng.next();
+                self.cx.x[9] = self.rng.next();
+            }
+            let op = self.random_op();
+            self.apply(op);
+            self.check_invariants(op);
+        }
+    }
+}
+
+#[test]
+fn random_operations_match_the_model() {
+    for seed in 0..SEEDS {
+        Harness::new(seed, usize::MAX).run();
+    }
+}
+
+#[test]
+fn random_operations_with_few_coroutines() {
+    // 协程少时阻塞、停住和死锁更常见
+    for seed in SEEDS..2 * SEEDS {
+        Harness::new(seed, 3).run();
+    }
+}
@@ -4,9 +4,15 @@ pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
 
 pub const PAGE_SIZE: usize = 0x1000;
+/// the stride of a task is `BIG_STRIDE / priority`
+pub const BIG_STRIDE: usize = 0x10_0000;
+/// priority of a task that never called `sys_set_priority`
+pub const DEFAULT_PRIORITY: usize = 16;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// page shared by the kernel and the process, through which user space switches coroutines
+pub const COROUTINE_SCHED_PAGE: usize = TRAP_CONTEXT - PAGE_SIZE;
 
 pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
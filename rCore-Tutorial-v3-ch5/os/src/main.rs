@@ -78,6 +78,10 @@ pub fn rust_main() -> ! {
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
     loader::list_apps();
+    println!("[kernel] scheduler: {}", task::scheduler_name());
+    if let Some(seed) = task::sched_seed() {
+        println!("[kernel] deterministic scheduling with SCHED_SEED={}", seed);
+    }
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
@@ -4,7 +4,9 @@ use super::{FrameTracker, frame_alloc};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{
+    COROUTINE_SCHED_PAGE, MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE,
+};
 use crate::sync::UPSafeCell;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
@@ -61,6 +63,57 @@ impl MemorySet {
             None,
         );
     }
+    /// Reserve a framed area without mapping any page of it; pages are mapped
+    /// later on demand through [`MemorySet::map_pages_in_area`].
+    pub fn insert_lazy_framed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        self.areas
+            .push(MapArea::new(start_va, end_va, MapType::Framed, permission));
+    }
+    /// Whether any area, mapped or not, has a page in `[start_va, end_va)`
+    pub fn overlaps(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+        self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+        })
+    }
+    /// Map the not yet mapped pages in `[start_va, end_va)` of the framed area
+    /// that starts with `area_start_vpn`, return false if there is no such area
+    /// or the range is out of it.
+    pub fn map_pages_in_area(
+        &mut self,
+        area_start_vpn: VirtPageNum,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+    ) -> bool {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+        if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() == area_start_vpn)
+        {
+            if area.map_type != MapType::Framed
+                || start_vpn < area.vpn_range.get_start()
+                || end_vpn > area.vpn_range.get_end()
+            {
+                return false;
+            }
+            for vpn in VPNRange::new(start_vpn, end_vpn) {
+                if !area.data_frames.contains_key(&vpn) {
+                    area.map_one(&mut self.page_table, vpn);
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
     ///Remove `MapArea` that starts with `start_vpn`
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -227,6 +280,16 @@ impl MemorySet {
             ),
             None,
         );
+        // map the coroutine scheduling page shared with user space
+        memory_set.push(
+            MapArea::new(
+                COROUTINE_SCHED_PAGE.into(),
+                TRAP_CONTEXT.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        );
         (
             memory_set,
             user_stack_top,
@@ -240,16 +303,18 @@ impl MemorySet {
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
+            let mut new_area = MapArea::from_another(area);
+            // only pages that are mapped in the lazily mapped areas are copied
+            for vpn in area.mapped_vpns() {
+                new_area.map_one(&mut memory_set.page_table, vpn);
+                // copy data from another space
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn
                     .get_bytes_array()
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
+            memory_set.areas.push(new_area);
         }
         memory_set
     }
@@ -330,10 +395,18 @@ impl MapArea {
         }
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
+        for vpn in self.mapped_vpns() {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// Pages that are actually mapped, which is a subset of `vpn_range` for
+    /// framed areas that are mapped lazily
+    pub fn mapped_vpns(&self) -> Vec<VirtPageNum> {
+        match self.map_type {
+            MapType::Identical => self.vpn_range.into_iter().collect(),
+            MapType::Framed => self.data_frames.keys().copied().collect(),
+        }
+    }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &PageTable, data: &[u8]) {
@@ -17,7 +17,9 @@ pub use frame_allocator::{FrameTracker, frame_alloc};
 pub use memory_set::remap_test;
 pub use memory_set::{KERNEL_SPACE, MapPermission, MemorySet};
 use page_table::{PTEFlags, PageTable};
-pub use page_table::{PageTableEntry, translated_byte_buffer, translated_refmut, translated_str};
+pub use page_table::{
+    PageTableEntry, copy_to_user, translated_byte_buffer, translated_refmut, translated_str,
+};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
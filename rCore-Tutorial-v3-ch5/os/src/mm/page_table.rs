@@ -1,6 +1,7 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
 use super::{FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum, frame_alloc};
+use crate::task::handle_coroutine_stack_fault;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -150,16 +151,37 @@ impl PageTable {
         8usize << 60 | self.root_ppn.0
     }
 }
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// translate a user virtual address through page table, `None` unless it is
+/// in a mapped user page
+///
+/// A page of a coroutine stack that is reserved but not touched by user space
+/// yet is mapped first, as a page fault there would.
+fn translate_user_va(page_table: &PageTable, va: VirtAddr) -> Option<PhysAddr> {
+    let mapped = || {
+        page_table
+            .translate(va.floor())
+            .is_some_and(|pte| pte.is_valid() && pte.flags().contains(PTEFlags::U))
+    };
+    if !mapped() && !(handle_coroutine_stack_fault(va.into()) && mapped()) {
+        return None;
+    }
+    page_table.translate_va(va)
+}
+/// translate a pointer to a mutable u8 Vec through page table, `None` if part
+/// of the buffer is not mapped
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Option<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translate_user_va(&page_table, start_va)?.floor();
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -170,18 +192,16 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
-/// translate a pointer to a mutable u8 Vec end with `\0` through page table to a `String`
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// translate a pointer to a mutable u8 Vec end with `\0` through page table to a `String`,
+/// `None` if part of it is not mapped
+pub fn translated_str(token: usize, ptr: *const u8) -> Option<String> {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let ch: u8 = *(translate_user_va(&page_table, VirtAddr::from(va))?.get_mut());
         if ch == 0 {
             break;
         } else {
@@ -189,16 +209,27 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
             va += 1;
         }
     }
-    string
+    Some(string)
 }
-///translate a generic through page table and return a mutable reference
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+///translate a generic through page table and return a mutable reference, `None` if it is not mapped
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
     //println!("into translated_refmut!");
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
     //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    translate_user_va(&page_table, VirtAddr::from(va)).map(|pa| pa.get_mut())
+}
+/// copy `data` into user space starting at `ptr`, which may cross page boundaries;
+/// return false, copying nothing, if part of the destination is not mapped
+pub fn copy_to_user(token: usize, ptr: *mut u8, data: &[u8]) -> bool {
+    let buffers = match translated_byte_buffer(token, ptr, data.len()) {
+        Some(buffers) => buffers,
+        None => return false,
+    };
+    let mut start = 0;
+    for buffer in buffers {
+        buffer.copy_from_slice(&data[start..start + buffer.len()]);
+        start += buffer.len();
+    }
+    true
 }
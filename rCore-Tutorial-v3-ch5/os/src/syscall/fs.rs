@@ -9,7 +9,10 @@ const FD_STDOUT: usize = 1;
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
+            let buffers = match translated_byte_buffer(current_user_token(), buf, len) {
+                Some(buffers) => buffers,
+                None => return -1,
+            };
             for buffer in buffers {
                 print!("{}", core::str::from_utf8(buffer).unwrap());
             }
@@ -25,6 +28,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDIN => {
             assert_eq!(len, 1, "Only support len = 1 in sys_read!");
+            let mut buffers = match translated_byte_buffer(current_user_token(), buf, len) {
+                Some(buffers) => buffers,
+                None => return -1,
+            };
             let mut c: usize;
             loop {
                 c = console_getchar();
@@ -36,7 +43,6 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
                 }
             }
             let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
             unsafe {
                 buffers[0].as_mut_ptr().write_volatile(ch);
             }
@@ -12,19 +12,44 @@
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SCHED_SETPOLICY: usize = 119;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_TIMES: usize = 153;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GET_TIME_US: usize = 170;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_SCHED_SET_DEADLINE: usize = 274;
 const SYSCALL_COROUTINE_CREATE: usize = 600;
 const SYSCALL_COROUTINE_YIELD: usize = 601;
 const SYSCALL_COROUTINE_RESUME: usize = 602;
 const SYSCALL_COROUTINE_EXIT: usize = 603;
+const SYSCALL_COROUTINE_WAITPID: usize = 604;
+const SYSCALL_COROUTINE_INFO: usize = 605;
+const SYSCALL_COROUTINE_STACK_USAGE: usize = 606;
+const SYSCALL_COROUTINE_FAULT_POLICY: usize = 607;
+const SYSCALL_COROUTINE_EXIT_POLICY: usize = 608;
+const SYSCALL_COROUTINE_DETACH: usize = 609;
+const SYSCALL_COROUTINE_STACK_CACHE_LIMIT: usize = 610;
+const SYSCALL_COROUTINE_TRIM_STACK_CACHE: usize = 611;
+const SYSCALL_COROUTINE_SET_NAME: usize = 612;
+const SYSCALL_COROUTINE_STATS: usize = 613;
+const SYSCALL_COROUTINE_DEADLOCK_POLICY: usize = 614;
+const SYSCALL_COROUTINE_SUSPEND: usize = 615;
+const SYSCALL_COROUTINE_NOTIFY: usize = 616;
+const SYSCALL_COROUTINE_WAIT_NOTIFY: usize = 617;
+const SYSCALL_COROUTINE_FAST_PATH: usize = 618;
+const SYSCALL_COROUTINE_UPCALL: usize = 619;
+const SYSCALL_COROUTINE_UPCALL_RETURN: usize = 620;
+const SYSCALL_COROUTINE_TRACE: usize = 621;
+const SYSCALL_TRACE_READ: usize = 622;
 mod fs;
 mod process;
 
+use crate::task::{CoroutineInfo, CoroutineStats, TaskTimes, TraceRecord};
 use fs::*;
 use process::*;
 /// handle syscall exception with `syscall_id` and other arguments
@@ -33,16 +58,42 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_SCHED_SETPOLICY => sys_sched_setpolicy(args[0], args[1]),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
+        SYSCALL_TIMES => sys_times(args[0] as *mut TaskTimes),
         SYSCALL_GET_TIME => sys_get_time(),
+        SYSCALL_GET_TIME_US => sys_get_time_us(),
         SYSCALL_COROUTINE_CREATE => sys_coroutine_create(args[0], args[1], args[2]),
-        SYSCALL_COROUTINE_YIELD => sys_coroutine_yield(),
+        SYSCALL_COROUTINE_YIELD => sys_coroutine_yield(args[0]),
         SYSCALL_COROUTINE_RESUME => sys_coroutine_resume(args[0]),
         SYSCALL_COROUTINE_EXIT => sys_coroutine_exit(args[0] as i32),
+        SYSCALL_COROUTINE_WAITPID => sys_coroutine_waitpid(args[0] as isize),
+        SYSCALL_COROUTINE_INFO => {
+            sys_coroutine_info(args[0] as isize, args[1] as *mut CoroutineInfo, args[2])
+        }
+        SYSCALL_COROUTINE_STACK_USAGE => sys_coroutine_stack_usage(args[0]),
+        SYSCALL_COROUTINE_FAULT_POLICY => sys_coroutine_fault_policy(args[0]),
+        SYSCALL_COROUTINE_EXIT_POLICY => sys_coroutine_exit_policy(args[0]),
+        SYSCALL_COROUTINE_DETACH => sys_coroutine_detach(args[0]),
+        SYSCALL_COROUTINE_STACK_CACHE_LIMIT => sys_coroutine_stack_cache_limit(args[0]),
+        SYSCALL_COROUTINE_TRIM_STACK_CACHE => sys_coroutine_trim_stack_cache(args[0]),
+        SYSCALL_COROUTINE_SET_NAME => sys_coroutine_set_name(args[0], args[1] as *const u8),
+        SYSCALL_COROUTINE_STATS => sys_coroutine_stats(args[0], args[1] as *mut CoroutineStats),
+        SYSCALL_COROUTINE_DEADLOCK_POLICY => sys_coroutine_deadlock_policy(args[0]),
+        SYSCALL_COROUTINE_SUSPEND => sys_coroutine_suspend(),
+        SYSCALL_COROUTINE_NOTIFY => sys_coroutine_notify(args[0], args[1], args[2]),
+        SYSCALL_COROUTINE_WAIT_NOTIFY => sys_coroutine_wait_notify(),
+        SYSCALL_COROUTINE_FAST_PATH => sys_coroutine_fast_path(args[0], args[1], args[2]),
+        SYSCALL_COROUTINE_UPCALL => sys_coroutine_upcall(args[0], args[1]),
+        SYSCALL_COROUTINE_UPCALL_RETURN => sys_coroutine_upcall_return(),
+        SYSCALL_COROUTINE_TRACE => sys_coroutine_trace(args[0]),
+        SYSCALL_TRACE_READ => sys_trace_read(args[0] as *mut TraceRecord, args[1]),
         SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_FORK => sys_fork(),
+        SYSCALL_FORK => sys_fork(args[0]),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_SCHED_SET_DEADLINE => sys_sched_set_deadline(args[0], args[1], args[2]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
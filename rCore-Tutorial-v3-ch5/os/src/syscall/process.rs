@@ -1,33 +1,166 @@
 use crate::loader::get_app_data_by_name;
-use crate::mm::{translated_refmut, translated_str};
+use crate::mm::{copy_to_user, translated_refmut, translated_str};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    DeadlineEntity, SchedPolicy, TaskTimes, add_task, admit_deadline, current_task, current_user_token,
+    exit_current_and_run_next, pid2task, set_sched_policy, suspend_current_and_run_next,
 };
-use crate::timer::get_time_ms;
+use crate::timer::{get_time_ms, get_time_us, set_next_trigger};
 use alloc::sync::Arc;
-use crate::task::{coroutine_create, coroutine_yield, coroutine_resume, coroutine_block};
-pub fn sys_exit(exit_code: i32) -> ! {
+use alloc::vec::Vec;
+use crate::task::{
+    COROUTINE_NAME_LEN, CoroutineInfo, CoroutineStats, DeadlockPolicy, ExitPolicy, FastPath,
+    FaultPolicy, ForkMode, TraceRecord, WaitReason, coroutine_block, coroutine_create,
+    coroutine_defer_exit, coroutine_detach, coroutine_enable_fast_path, coroutine_exit,
+    coroutine_notify, coroutine_resume, coroutine_set_deadlock_policy, coroutine_set_name,
+    coroutine_set_stack_cache_limit, coroutine_set_trace, coroutine_set_upcall,
+    coroutine_stack_high_watermark, coroutine_stats, coroutine_trim_stack_cache,
+    coroutine_upcall_return, coroutine_wait_notify, coroutine_yield, is_deterministic, read_trace,
+};
+/// Exit the current process, or park the main flow until the other coroutines
+/// exit if the process chose to wait for them. Only returns in the latter case.
+pub fn sys_exit(exit_code: i32) -> isize {
+    if let Some(ret) = coroutine_defer_exit(exit_code) {
+        return ret;
+    }
     exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
 pub fn sys_yield() -> isize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .sched
+        .end_job();
     suspend_current_and_run_next();
     0
 }
 
+/// Set the priority of the current process; a process gets CPU time in proportion
+/// to its priority. Return `prio`, or -1 if it is less than 2.
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio < 2 {
+        return -1;
+    }
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .sched
+        .set_priority(prio as usize);
+    prio
+}
+
+/// Switch the process scheduling policy: 0 FIFO, 1 round robin with a quantum
+/// of `quantum_us` microseconds (0 for the default one), 2 MLFQ, 3 CFS and
+/// 4 stride scheduling. The ready processes move over to the new policy.
+/// Return the previous policy, or -1 if `policy` is unknown or scheduling is
+/// deterministic.
+pub fn sys_sched_setpolicy(policy: usize, quantum_us: usize) -> isize {
+    let policy = match policy {
+        0 => SchedPolicy::Fifo,
+        1 => SchedPolicy::RoundRobin,
+        2 => SchedPolicy::Mlfq,
+        3 => SchedPolicy::Cfs,
+        4 => SchedPolicy::Stride,
+        _ => return -1,
+    };
+    if is_deterministic() {
+        return -1;
+    }
+    let previous = set_sched_policy(policy, quantum_us);
+    // the time slice of the current process may have changed
+    set_next_trigger();
+    match previous {
+        SchedPolicy::Fifo => 0,
+        SchedPolicy::RoundRobin => 1,
+        SchedPolicy::Mlfq => 2,
+        SchedPolicy::Cfs => 3,
+        SchedPolicy::Stride => 4,
+    }
+}
+
+/// Make the current process a real-time one that needs `runtime_us` of CPU
+/// time every `period_us`, done within `deadline_us` of the start of each
+/// period. Real-time processes run earliest deadline first ahead of all the
+/// others; one that yields is done for the current period, and one that uses
+/// up its runtime is throttled until the next. `runtime_us` 0 makes the process
+/// a normal one again.
+/// Return 0, -1 if the parameters do not satisfy runtime <= deadline <= period
+/// <= 60 s or scheduling is deterministic, or -2 if the total utilization of
+/// the real-time processes would exceed the bound.
+pub fn sys_sched_set_deadline(runtime_us: usize, period_us: usize, deadline_us: usize) -> isize {
+    if is_deterministic() {
+        return -1;
+    }
+    let now = get_time_us();
+    let deadline = if runtime_us == 0 {
+        None
+    } else {
+        match DeadlineEntity::new(runtime_us, period_us, deadline_us, now) {
+            Some(deadline) => Some(deadline),
+            None => return -1,
+        }
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !admit_deadline(inner.sched.deadline.as_ref(), deadline.as_ref()) {
+        return -2;
+    }
+    inner.sched.deadline = deadline;
+    // the first job starts now
+    inner.sched.switch_in(now);
+    drop(inner);
+    // the budget replaces the time slice
+    set_next_trigger();
+    0
+}
+
+/// Write the CPU time and context switches of the current process, and those
+/// of its waited-for children, into the [`TaskTimes`] at `times`.
+/// Return 0, or -1 if `times` is not mapped.
+pub fn sys_times(times: *mut TaskTimes) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    // include the time of this system call so far
+    inner.account_kernel_time(get_time_us());
+    let task_times = inner.times;
+    let token = inner.get_user_token();
+    drop(inner);
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            &task_times as *const TaskTimes as *const u8,
+            core::mem::size_of::<TaskTimes>(),
+        )
+    };
+    if !copy_to_user(token, times as *mut u8, data) {
+        return -1;
+    }
+    0
+}
+
 pub fn sys_get_time() -> isize {
     get_time_ms() as isize
 }
 
+/// Get the current time in microseconds, for measuring short intervals.
+pub fn sys_get_time_us() -> isize {
+    get_time_us() as isize
+}
+
 pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
-pub fn sys_fork() -> isize {
+/// Fork the current process. `mode` 0 copies every coroutine of the process,
+/// 1 keeps only the calling coroutine, which becomes the main flow of the child.
+pub fn sys_fork(mode: usize) -> isize {
+    let mode = match mode {
+        0 => ForkMode::AllCoroutines,
+        1 => ForkMode::CurrentCoroutine,
+        _ => return -1,
+    };
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = current_task.fork(mode);
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -36,12 +169,17 @@ pub fn sys_fork() -> isize {
     trap_cx.x[10] = 0;
     // add new task to scheduler
     add_task(new_task);
+    // the current process no longer runs alone, time its slice
+    set_next_trigger();
     new_pid as isize
 }
 
 pub fn sys_exec(path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match translated_str(token, path) {
+        Some(path) => path,
+        None => return -1,
+    };
     if let Some(data) = get_app_data_by_name(path.as_str()) {
         let task = current_task().unwrap();
         task.exec(data);
@@ -51,41 +189,293 @@ pub fn sys_exec(path: *const u8) -> isize {
     }
 }
 pub fn sys_coroutine_create(entry: usize, arg: usize, stack_size: usize) -> isize {
-    let coroutine = coroutine_create(entry, arg, stack_size);
-    coroutine.cid as isize
+    match coroutine_create(entry, arg, stack_size) {
+        Some(coroutine) => coroutine.cid as isize,
+        // 栈大小不合法
+        None => -1,
+    }
 }
 
-// 协程主动让出CPU
-pub fn sys_coroutine_yield() -> isize {
-    if coroutine_yield() {
-        0
-    } else {
-        -1 // 无可用协程
-    }
+// 协程主动让出CPU，user_cx 非0时是协程在用户态保存寄存器的位置
+pub fn sys_coroutine_yield(user_cx: usize) -> isize {
+    // 成功切换时返回值属于被切换到的协程，-1 表示无可用协程
+    coroutine_yield(user_cx)
 }
 
 // 恢复指定协程的执行
 pub fn sys_coroutine_resume(cid: usize) -> isize {
-    if coroutine_resume(cid) {
-        0
-    } else {
-        -1 // 协程不存在或已经在运行
-    }
+    // -1 表示协程不存在、已经退出或正在运行
+    coroutine_resume(cid)
 }
 
 // 协程退出
 pub fn sys_coroutine_exit(exit_code: i32) -> isize {
-    // 设置当前协程为退出状态，然后切换到下一个协程
-    coroutine_block();
-    if coroutine_yield() {
-        exit_code as isize
+    // 设置当前协程为退出状态，然后切换到下一个协程；主执行流调用时返回 -1
+    coroutine_exit(exit_code)
+}
+
+// 查询协程栈的最大使用量（字节），用来为不同的协程挑选合适的栈大小
+pub fn sys_coroutine_stack_usage(cid: usize) -> isize {
+    // -1 表示协程不存在或没有独立的栈
+    coroutine_stack_high_watermark(cid)
+}
+
+/// Choose what a fatal exception in a coroutine other than the main flow kills:
+/// 0 for the whole process (the default), 1 for just the faulting coroutine.
+///
+/// Return the previous policy, or -1 if `policy` is invalid.
+pub fn sys_coroutine_fault_policy(policy: usize) -> isize {
+    let policy = match policy {
+        0 => FaultPolicy::KillProcess,
+        1 => FaultPolicy::KillCoroutine,
+        _ => return -1,
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.coroutine_manager.set_fault_policy(policy) {
+        FaultPolicy::KillProcess => 0,
+        FaultPolicy::KillCoroutine => 1,
+    }
+}
+
+/// Choose what happens to live coroutines when the main flow exits the process:
+/// 0 kills them all (the default), 1 waits for every coroutine that is not detached.
+///
+/// Return the previous policy, or -1 if `policy` is invalid.
+pub fn sys_coroutine_exit_policy(policy: usize) -> isize {
+    let policy = match policy {
+        0 => ExitPolicy::KillAll,
+        1 => ExitPolicy::WaitAll,
+        _ => return -1,
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.coroutine_manager.set_exit_policy(policy) {
+        ExitPolicy::KillAll => 0,
+        ExitPolicy::WaitAll => 1,
+    }
+}
+
+pub fn sys_coroutine_detach(cid: usize) -> isize {
+    coroutine_detach(cid)
+}
+
+/// Set how many stacks of exited coroutines are kept for reuse, freeing the ones
+/// over the new limit. Return the previous limit.
+pub fn sys_coroutine_stack_cache_limit(limit: usize) -> isize {
+    coroutine_set_stack_cache_limit(limit) as isize
+}
+
+/// Free cached coroutine stacks until at most `keep` remain.
+/// Return the number of stacks freed.
+pub fn sys_coroutine_trim_stack_cache(keep: usize) -> isize {
+    coroutine_trim_stack_cache(keep) as isize
+}
+
+/// Copy the scheduling statistics of coroutine `cid` of the current process to `stats`.
+///
+/// Return 0, or -1 if there is no such coroutine or `stats` is not mapped.
+pub fn sys_coroutine_stats(cid: usize, stats: *mut CoroutineStats) -> isize {
+    match coroutine_stats(cid) {
+        Some(coroutine_stats) => {
+            let data = unsafe {
+                core::slice::from_raw_parts(
+                    &coroutine_stats as *const CoroutineStats as *const u8,
+                    core::mem::size_of::<CoroutineStats>(),
+                )
+            };
+            if copy_to_user(current_user_token(), stats as *mut u8, data) {
+                0
+            } else {
+                -1
+            }
+        }
+        None => -1,
+    }
+}
+
+/// Name coroutine `cid` of the current process, keeping at most
+/// `COROUTINE_NAME_LEN - 1` bytes of the string at `name`.
+///
+/// Return 0, or -1 if there is no such coroutine or `name` is not mapped.
+pub fn sys_coroutine_set_name(cid: usize, name: *const u8) -> isize {
+    let token = current_user_token();
+    let name = match translated_str(token, name) {
+        Some(name) => name,
+        None => return -1,
+    };
+    let name: Vec<u8> = name.chars().take(COROUTINE_NAME_LEN - 1).map(|ch| ch as u8).collect();
+    coroutine_set_name(cid, &name)
+}
+
+/// Choose what happens when every coroutine of the process is blocked on
+/// something that can no longer happen: 0 reports them (the default), 1 also
+/// wakes the main flow with an error, 2 also kills the process.
+///
+/// Return the previous policy, or -1 if `policy` is invalid.
+pub fn sys_coroutine_deadlock_policy(policy: usize) -> isize {
+    let policy = match policy {
+        0 => DeadlockPolicy::Report,
+        1 => DeadlockPolicy::Error,
+        2 => DeadlockPolicy::Kill,
+        _ => return -1,
+    };
+    match coroutine_set_deadlock_policy(policy) {
+        DeadlockPolicy::Report => 0,
+        DeadlockPolicy::Error => 1,
+        DeadlockPolicy::Kill => 2,
+    }
+}
+
+/// Wake coroutine `cid` of process `pid` waiting for a notification, handing it
+/// `value`. `pid` must be the calling process, its parent or one of its children.
+/// A coroutine that is not waiting keeps one notification until it waits.
+///
+/// Return 0 if the coroutine was woken, 1 if the notification was kept, -1 if
+/// there is no such process or coroutine, -2 if one is already kept.
+pub fn sys_coroutine_notify(pid: usize, cid: usize, value: usize) -> isize {
+    coroutine_notify(pid, cid, value)
+}
+
+/// Let user space switch between ready coroutines without trapping, through the
+/// scheduling page at `COROUTINE_SCHED_PAGE`. `restore_entry` loads the registers
+/// a coroutine saved when it switched out in user space; a trap while the pc is in
+/// `[begin, end)`, the code that updates the page, restarts that code from `begin`.
+///
+/// Return 0, or -1 if the range is empty or deterministic scheduling is on, in
+/// which case every coroutine switch has to go through the kernel.
+pub fn sys_coroutine_fast_path(restore_entry: usize, begin: usize, end: usize) -> isize {
+    if begin >= end || is_deterministic() {
+        return -1;
+    }
+    coroutine_enable_fast_path(FastPath {
+        restore_entry,
+        begin,
+        end,
+    });
+    0
+}
+
+/// Upcall `entry` whenever a coroutine of the calling process blocks in the kernel
+/// (event 0 in a0) or is woken up again (event 1), with the coroutine id in a1. The
+/// handler runs on a coroutine of its own with a `stack_size` stack, created on the
+/// first call; later calls only change `entry`, and 0 pauses the upcalls. It ends
+/// each event with `sys_coroutine_upcall_return` and must not block itself.
+///
+/// Return the id of the handler coroutine, or -1 if pausing before registering.
+pub fn sys_coroutine_upcall(entry: usize, stack_size: usize) -> isize {
+    coroutine_set_upcall(entry, stack_size)
+}
+
+/// Finish handling an upcall event. Restarts the handler with the next event if
+/// one is pending, otherwise switches to another coroutine.
+///
+/// Return -1 if not called from the upcall handler.
+pub fn sys_coroutine_upcall_return() -> isize {
+    coroutine_upcall_return()
+}
+
+/// Turn tracing of coroutine events of the calling process on (`enable` != 0) or
+/// off. Tracing stays on across exec but is not inherited by forked children.
+///
+/// Return 1 if tracing was on before, 0 otherwise.
+pub fn sys_coroutine_trace(enable: usize) -> isize {
+    coroutine_set_trace(enable != 0) as isize
+}
+
+/// Move at most `len` of the oldest records out of the kernel trace buffer,
+/// which holds the records of every traced process, into `buf`.
+///
+/// Return the number of records written, or -1 if `buf` is not mapped, in
+/// which case the records are lost.
+pub fn sys_trace_read(buf: *mut TraceRecord, len: usize) -> isize {
+    let records = read_trace(len);
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            records.as_ptr() as *const u8,
+            records.len() * core::mem::size_of::<TraceRecord>(),
+        )
+    };
+    if !copy_to_user(current_user_token(), buf as *mut u8, data) {
+        return -1;
+    }
+    records.len() as isize
+}
+
+/// Park the calling coroutine until it is notified, returning the value sent.
+pub fn sys_coroutine_wait_notify() -> isize {
+    coroutine_wait_notify()
+}
+
+/// Park the calling coroutine until another coroutine resumes it.
+pub fn sys_coroutine_suspend() -> isize {
+    coroutine_block(WaitReason::Suspended)
+}
+
+/// Park the calling coroutine until the child `pid` (or any child if `pid` is -1)
+/// becomes a zombie. Other coroutines of the process keep running meanwhile.
+///
+/// Return -1 if there is no such child. Otherwise return 0 once such a child has
+/// exited; the caller reaps it with `sys_waitpid` afterwards.
+pub fn sys_coroutine_waitpid(pid: isize) -> isize {
+    let task = current_task().unwrap();
+    // ---- access current TCB exclusively
+    let inner = task.inner_exclusive_access();
+    let mut children = inner
+        .children
+        .iter()
+        .filter(|p| pid == -1 || pid as usize == p.getpid())
+        .peekable();
+    if children.peek().is_none() {
+        return -1;
+    }
+    if children.any(|p| p.inner_exclusive_access().is_zombie()) {
+        return 0;
+    }
+    drop(inner);
+    drop(task);
+    // ---- release current PCB
+    // woken up from `exit_current_and_run_next` of the child
+    coroutine_block(WaitReason::Child(pid))
+}
+/// Copy information about the coroutines of process `pid` into `buf`, which
+/// holds at most `len` entries. `pid` is -1 for the calling process, otherwise
+/// any live process, so that a debugging tool can inspect the others.
+///
+/// Return -1 if there is no such process or `buf` is not mapped, else the
+/// number of coroutines the process has, which may be larger than `len`.
+pub fn sys_coroutine_info(pid: isize, buf: *mut CoroutineInfo, len: usize) -> isize {
+    let target = if pid == -1 {
+        current_task().unwrap()
     } else {
-        -1 // 无可用协程
+        match pid2task(pid as usize) {
+            Some(task) => task,
+            None => return -1,
+        }
+    };
+    let infos = target.inner_exclusive_access().coroutine_manager.coroutine_infos();
+    let count = infos.len().min(len);
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            infos.as_ptr() as *const u8,
+            count * core::mem::size_of::<CoroutineInfo>(),
+        )
+    };
+    if !copy_to_user(current_user_token(), buf as *mut u8, data) {
+        return -1;
     }
+    infos.len() as isize
 }
+
 /// If there is not a child process whose pid is same as given, return -1.
 /// Else if there is a child process but it is still running, return -2.
+/// Return -1 too if `exit_code_ptr` is not mapped.
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    // before the TCB is borrowed, as it may map a coroutine stack page
+    let exit_code_ref = match translated_refmut(current_user_token(), exit_code_ptr) {
+        Some(exit_code_ref) => exit_code_ref,
+        None => return -1,
+    };
     let task = current_task().unwrap();
     // find a child process
 
@@ -110,9 +500,12 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         assert_eq!(Arc::strong_count(&child), 1);
         let found_pid = child.getpid();
         // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
+        let child_inner = child.inner_exclusive_access();
+        let exit_code = child_inner.exit_code;
+        inner.times.add_child(&child_inner.times);
+        drop(child_inner);
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+        *exit_code_ref = exit_code;
         found_pid as isize
     } else {
         -2
@@ -1,11 +1,41 @@
 // src/task/coroutine.rs
+use crate::config::PAGE_SIZE;
 use crate::sync::UPSafeCell;
-use crate::task::context::TaskContext; // 引入已有的TaskContext
 use alloc::sync::{Arc};
 use alloc::vec::Vec;
 use alloc::collections::VecDeque;
 use core::cell::RefMut;
-use crate::task::switch::__switch;
+use core::fmt;
+
+/// 主执行流的协程ID，每个进程创建时都自带这个协程
+pub const MAIN_CID: usize = 0;
+
+/// 协程栈在用户地址空间中的起始分配位置
+const COROUTINE_STACK_REGION: usize = 0x8000_0000;
+
+/// 协程名字占用的字节数，名字最长为这个长度减一，其余字节填0
+pub const COROUTINE_NAME_LEN: usize = 16;
+
+/// 单个协程栈最大的大小
+pub const MAX_STACK_SIZE: usize = 1024 * 1024;
+
+/// 每个进程最多保留的未分离的已退出协程数量，它们的退出码和统计信息仍可查询，
+/// 更早退出的协程的控制块被回收
+pub const EXITED_RETAIN_LIMIT: usize = 32;
+
+/// 每个进程默认最多缓存的协程栈数量
+pub const DEFAULT_STACK_CACHE_LIMIT: usize = 8;
+
+/// 已退出协程留下的栈，保留的地址范围和已映射的页都原样保留，供新协程复用
+#[derive(Copy, Clone)]
+struct CachedStack {
+    /// 栈的起始地址
+    base: usize,
+    /// 保留的栈大小
+    size: usize,
+    /// 已映射部分的最低地址
+    mapped_base: usize,
+}
 
 /// 协程的状态枚举
 #[derive(Copy, Clone, PartialEq,Debug)]
@@ -20,37 +50,314 @@ pub enum CoroutineStatus {
     Exited,
 }
 
+/// 协程阻塞时等待的事件
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WaitReason {
+    /// 被显式挂起，只能通过 resume 唤醒
+    Suspended,
+    /// 等待指定子进程变为僵尸进程，-1 表示任意子进程
+    Child(isize),
+    /// 等待其他协程或其他进程用 notify 发来的值
+    Notify,
+    /// 主执行流要退出进程，等待所有未分离的协程退出
+    Coroutines,
+    /// 运行上行调用处理函数的协程在等待下一个事件
+    Upcall,
+}
+
+impl fmt::Display for WaitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitReason::Suspended => write!(f, "a resume"),
+            WaitReason::Child(-1) => write!(f, "any child to exit"),
+            WaitReason::Child(pid) => write!(f, "child {} to exit", pid),
+            WaitReason::Coroutines => write!(f, "other coroutines to exit"),
+            WaitReason::Notify => write!(f, "a notification"),
+            WaitReason::Upcall => write!(f, "a scheduler event"),
+        }
+    }
+}
+
+/// 协程中发生缺页、非法指令等致命异常时的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultPolicy {
+    /// 终止整个进程（默认）
+    KillProcess,
+    /// 只终止出错的协程，主执行流中的异常仍然终止整个进程
+    KillCoroutine,
+}
+
+/// 死锁时被终止的进程的退出码，也是死锁时主执行流的阻塞系统调用得到的错误码
+pub const DEADLOCK_EXIT_CODE: i32 = -4;
+
+/// 进程内所有协程都阻塞、且没有任何事件能唤醒它们时的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DeadlockPolicy {
+    /// 报告阻塞的协程以及它们等待的事件，然后继续等待（默认）
+    Report,
+    /// 报告后唤醒主执行流，它的阻塞系统调用返回 [`DEADLOCK_EXIT_CODE`]；
+    /// 主执行流正在等待其他协程退出时无法接收错误，按 [`DeadlockPolicy::Kill`] 处理
+    Error,
+    /// 报告后以 [`DEADLOCK_EXIT_CODE`] 终止进程
+    Kill,
+}
+
+/// 主执行流退出进程时对仍未退出的协程的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExitPolicy {
+    /// 直接终止所有协程（默认）
+    KillAll,
+    /// 等待所有未分离的协程退出后再退出进程，分离的协程随进程一起终止
+    WaitAll,
+}
+
+/// fork时子进程继承协程的方式
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ForkMode {
+    /// 复制所有协程，包括它们的栈和保存的寄存器（默认）
+    AllCoroutines,
+    /// 只保留调用fork的协程，它在子进程中成为主执行流
+    CurrentCoroutine,
+}
+
+/// 通过上行调用交给用户态运行时的事件，值就是处理函数在 a0 中得到的值
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum UpcallEvent {
+    /// 协程在内核中阻塞
+    Blocked = 0,
+    /// 阻塞的协程被唤醒，重新就绪
+    Unblocked = 1,
+}
+
+/// 进程登记的上行调用处理函数
+#[derive(Clone)]
+struct Upcall {
+    /// 运行处理函数的协程ID
+    cid: usize,
+    /// 处理函数的入口地址，为0时暂停通知
+    entry: usize,
+    /// 还没有交给处理函数的事件，以及事件涉及的协程ID
+    events: VecDeque<(UpcallEvent, usize)>,
+}
+
+/// 通过 sys_coroutine_stats 返回给用户态的单个协程的调度统计
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CoroutineStats {
+    /// 协程ID
+    pub cid: usize,
+    /// 协程被调度运行的次数
+    pub schedule_count: usize,
+    /// 协程通过让出、恢复其他协程、阻塞或退出主动切换出去的次数
+    pub voluntary_switches: usize,
+    /// 协程运行时所属进程被时钟中断抢占的次数
+    pub involuntary_switches: usize,
+    /// 协程占用CPU的累计时间（微秒），不含进程被换下CPU的时间
+    pub run_time_us: usize,
+    /// 协程处于阻塞状态的累计时间（微秒）
+    pub blocked_time_us: usize,
+}
+
+/// 通过 sys_coroutine_info 返回给用户态的单个协程信息
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CoroutineInfo {
+    /// 协程ID
+    pub cid: usize,
+    /// 协程状态，按 [`CoroutineStatus`] 的声明顺序编号
+    pub status: usize,
+    /// 协程入口点函数的地址
+    pub entry: usize,
+    /// 协程栈的起始地址
+    pub stack_base: usize,
+    /// 协程栈大小
+    pub stack_size: usize,
+    /// 协程被切换进来的次数
+    pub switch_count: usize,
+    /// 协程作为当前协程累计运行的时间（微秒）
+    pub run_time_us: usize,
+    /// 协程的退出码，仅在已退出时有意义
+    pub exit_code: isize,
+    /// 协程的名字，以0结尾，没有名字时全为0
+    pub name: [u8; COROUTINE_NAME_LEN],
+}
+
+/// 调度页中就绪环的容量，队首下标与当前协程ID共用一个字，因此不能超过256
+pub const SCHED_READY_CAPACITY: usize = 128;
+
+/// 调度页就绪环中的一项
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SchedEntry {
+    /// 协程ID
+    pub cid: usize,
+    /// 协程在用户态切换出去时保存寄存器的地址；为0表示寄存器由内核保存，只能经系统调用切换过去
+    pub user_cx: usize,
+}
+
+/// 内核与进程共享的协程调度页，映射在用户地址空间的 `COROUTINE_SCHED_PAGE` 处
+///
+/// 内核在返回用户态前把当前协程和就绪队列写入页中，user_lib 在就绪协程之间切换时
+/// 直接修改页中的就绪环，不再陷入内核。用户态的一次切换在写入 `current_head`
+/// 这一个字时才生效，在此之前陷入内核会让它从头重新执行，因此内核在陷入时总能读到一致的状态
+#[repr(C)]
+pub struct CoroutineSchedPage {
+    /// (当前协程ID << 8) | 就绪环队首的下标
+    pub current_head: usize,
+    /// 就绪环中的协程数量
+    pub ready_len: usize,
+    /// 为1时就绪环完整反映了就绪队列，用户态才可以在其中切换
+    pub valid: usize,
+    /// 就绪环，从队首开始依次是就绪队列中的协程
+    pub ready: [SchedEntry; SCHED_READY_CAPACITY],
+    /// 为1时进程的浮点单元已经打开，用户态切换协程时才需要保存浮点寄存器
+    pub fp_enabled: usize,
+}
+
+/// 进程通过 sys_coroutine_fast_path 登记的用户态切换代码
+#[derive(Copy, Clone, Debug)]
+pub struct FastPath {
+    /// 用户态恢复例程的地址，它从 a0 指向的位置装入协程保存的寄存器
+    pub restore_entry: usize,
+    /// 修改调度页的代码段的起始地址，在 [begin, end) 中陷入内核时从 begin 重新执行
+    pub begin: usize,
+    /// 修改调度页的代码段的结束地址，即生效的那条写指令之后
+    pub end: usize,
+}
+
+/// 协程跟踪事件的类型，值就是 [`TraceRecord::kind`]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TraceKind {
+    /// 协程被创建，参数是创建它的协程ID
+    Create = 0,
+    /// 协程成为当前协程
+    SwitchIn = 1,
+    /// 协程不再是当前协程
+    SwitchOut = 2,
+    /// 协程阻塞
+    Block = 3,
+    /// 阻塞的协程被唤醒
+    Unblock = 4,
+    /// 协程退出，参数是退出码
+    Exit = 5,
+    /// 跟踪缓冲区满了以后丢弃了最早的记录，参数是丢弃的条数，pid和协程ID都为0
+    Lost = 6,
+}
+
+/// 一条协程跟踪记录，通过 sys_trace_read 返回给用户态
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TraceRecord {
+    /// 事件发生的时间（微秒）
+    pub time_us: usize,
+    /// 协程所属进程的pid
+    pub pid: usize,
+    /// 协程ID
+    pub cid: usize,
+    /// 事件类型，按 [`TraceKind`] 的值编号
+    pub kind: usize,
+    /// 与事件类型有关的参数
+    pub arg: usize,
+}
+
+/// 协程切换中与体系结构相关的部分：保存的寄存器，以及计时用的时钟
+///
+/// 协程管理器只维护协程的状态和各个队列，寄存器的保存、装入和修改都通过这个 trait 完成。
+/// 内核中由陷入上下文实现；换成其他实现后，调度状态机不依赖内核的其余部分，可以在主机上测试
+pub trait SwitchBackend: Copy {
+    /// 协程第一次被切换进来时的寄存器：从 `entry` 开始执行，栈顶为 `stack_top`，第0个参数为 `arg`
+    fn initial(entry: usize, stack_top: usize, arg: usize) -> Self;
+
+    /// 把协程 `saved` 中保存的寄存器装入正在使用的上下文，与协程无关的部分保持不变
+    fn load(&mut self, saved: &Self);
+
+    /// 第 `index` 个参数寄存器的值，第0个同时是系统调用的返回值
+    fn arg(&self, index: usize) -> usize;
+
+    /// 设置第 `index` 个参数寄存器
+    fn set_arg(&mut self, index: usize, value: usize);
+
+    /// 设置返回用户态后继续执行的地址
+    fn set_pc(&mut self, pc: usize);
+
+    /// 设置栈指针
+    fn set_sp(&mut self, sp: usize);
+
+    /// 当前时间（微秒）
+    fn now_us() -> usize;
+
+    /// 确定性调度时在 `bound` 个就绪协程中选出下一个运行的下标，否则返回None，按先进先出的顺序调度
+    fn sched_random(bound: usize) -> Option<usize>;
+}
+
 /// 协程控制块，管理单个协程的所有信息
-pub struct CoroutineControlBlock {
-    /// 协程的唯一标识ID
+pub struct CoroutineControlBlock<B> {
+    /// 协程在所属进程内的唯一标识ID
     pub cid: usize,
     /// 协程的内部数据，使用UPSafeCell包装以确保安全访问
-    inner: UPSafeCell<CoroutineInner>,
+    inner: UPSafeCell<CoroutineInner<B>>,
 }
 
 /// 协程的内部数据结构
-pub struct CoroutineInner {
+#[derive(Clone)]
+pub struct CoroutineInner<B> {
     /// 协程当前的状态
     pub status: CoroutineStatus,
-    /// 协程的上下文信息，使用系统已有的TaskContext
-    pub context: TaskContext,
+    /// 协程被切换出去时保存的用户态寄存器
+    pub trap_cx: B,
     /// 协程栈的虚拟地址空间起始地址
     pub stack_base: usize,
-    /// 协程栈大小
+    /// 协程栈大小，即为协程保留的地址范围，栈最多只能增长到这么大
     pub stack_size: usize,
+    /// 协程栈中已经映射部分的起始地址，[stack_mapped_base, stack_base + stack_size) 已映射
+    pub stack_mapped_base: usize,
     /// 协程入口点函数的地址
     pub entry: usize,
     /// 协程函数的参数
     pub arg: usize,
+    /// 协程在用户态切换出去时保存寄存器的地址，此时 `trap_cx` 中的寄存器已经过时
+    pub user_cx: Option<usize>,
+    /// 协程处于阻塞状态时等待的事件
+    pub wait_reason: Option<WaitReason>,
+    /// 下次被切换进来时代替 a0 的值，用于把唤醒的原因交给阻塞的系统调用
+    pub wake_ret: Option<isize>,
+    /// 运行上行调用处理函数的协程下次被切换进来时，从处理函数入口 `.0` 开始处理事件 `.1`，
+    /// 事件涉及的协程ID为 `.2`
+    pub upcall_start: Option<(usize, UpcallEvent, usize)>,
+    /// 协程没有在等待时收到的通知，下次等待时立即得到
+    pub pending_notify: Option<usize>,
+    /// 协程的退出码，仅在 Exited 状态下有意义
+    pub exit_code: i32,
+    /// 协程被切换进来的次数
+    pub switch_count: usize,
+    /// 协程主动切换出去的次数
+    pub voluntary_switches: usize,
+    /// 协程运行时进程被时钟中断抢占的次数
+    pub involuntary_switches: usize,
+    /// 协程占用CPU的累计时间（微秒），不含当前这一段
+    pub run_time_us: usize,
+    /// 协程这一段开始占用CPU的时间（微秒），不占用CPU时为None
+    pub running_since_us: Option<usize>,
+    /// 协程处于阻塞状态的累计时间（微秒），不含当前这一段
+    pub blocked_time_us: usize,
+    /// 协程最近一次被阻塞的时间（微秒）
+    pub blocked_since_us: usize,
+    /// 协程退出时测得的栈最大使用量（字节）
+    pub stack_high_watermark: usize,
+    /// 协程是否已分离：分离的协程不会推迟进程退出，退出后立即被回收
+    pub detached: bool,
+    /// 用于诊断的名字，以0结尾，没有名字时全为0
+    pub name: [u8; COROUTINE_NAME_LEN],
 }
 
 
 
-impl CoroutineControlBlock {
+impl<B: SwitchBackend> CoroutineControlBlock<B> {
     /// 创建新协程控制块
     ///
     /// # 参数
     ///
+    /// * `cid` - 协程ID
     /// * `entry` - 协程入口函数的地址
     /// * `arg` - 传递给协程函数的参数
     /// * `stack_size` - 分配给协程的栈大小
@@ -59,115 +366,964 @@ impl CoroutineControlBlock {
     /// # 返回值
     ///
     /// 返回一个新的协程控制块实例
-    pub fn new(entry: usize, arg: usize, stack_size: usize, stack_base: usize) -> Self {
-        // 为协程分配一个唯一的ID
-        static mut NEXT_CID: usize = 0;
-        let cid = unsafe {
-            NEXT_CID += 1;
-            NEXT_CID
-        };
-
+    pub fn new(cid: usize, entry: usize, arg: usize, stack_size: usize, stack_base: usize) -> Self {
         // 计算栈指针位置（栈从高地址向低地址增长）
         let stack_top = stack_base + stack_size;
 
+        // 协程第一次被切换进来时从 entry 开始执行，参数放在 a0 中；
+        // 内核相关的字段在切换时不会被装入，这里留空即可
+        let trap_cx = B::initial(entry, stack_top, arg);
+
         Self {
             cid,
             inner: unsafe {
                 UPSafeCell::new(CoroutineInner {
                     status: CoroutineStatus::Ready,
-                    // 使用TaskContext::goto_trap_return初始化上下文
-                    // 这里假设协程恢复时也需要通过trap_return机制
-                    context: TaskContext::goto_trap_return(stack_top),
+                    trap_cx,
                     stack_base,
                     stack_size,
+                    stack_mapped_base: stack_top,
                     entry,
                     arg,
+                    user_cx: None,
+                    wait_reason: None,
+                    wake_ret: None,
+                    upcall_start: None,
+                    pending_notify: None,
+                    exit_code: 0,
+                    switch_count: 0,
+                    voluntary_switches: 0,
+                    involuntary_switches: 0,
+                    run_time_us: 0,
+                    running_since_us: None,
+                    blocked_time_us: 0,
+                    blocked_since_us: 0,
+                    stack_high_watermark: 0,
+                    detached: false,
+                    name: [0; COROUTINE_NAME_LEN],
                 })
             },
         }
     }
 
+    /// 为fork出的子进程复制协程控制块
+    ///
+    /// # 参数
+    ///
+    /// * `cid` - 协程在子进程中的ID
+    ///
+    /// # 返回值
+    ///
+    /// 返回状态、栈和保存的寄存器都与原协程相同的协程控制块
+    pub fn fork(&self, cid: usize) -> Self {
+        Self {
+            cid,
+            inner: unsafe { UPSafeCell::new(self.inner_exclusive_access().clone()) },
+        }
+    }
+
     /// 获取协程内部数据的可变引用
     ///
     /// # 返回值
     ///
     /// 返回对协程内部数据的独占访问引用
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, CoroutineInner> {
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, CoroutineInner<B>> {
         self.inner.exclusive_access()
     }
+
+    /// 生成协程的统计信息
+    pub fn info(&self) -> CoroutineInfo {
+        let inner = self.inner_exclusive_access();
+        let run_time_us = inner.run_time_at(B::now_us());
+        CoroutineInfo {
+            cid: self.cid,
+            status: inner.status as usize,
+            entry: inner.entry,
+            stack_base: inner.stack_base,
+            stack_size: inner.stack_size,
+            switch_count: inner.switch_count,
+            run_time_us,
+            exit_code: inner.exit_code as isize,
+            name: inner.name,
+        }
+    }
+
+    /// 生成协程的调度统计，正在进行的运行或阻塞也计算在内
+    pub fn stats(&self) -> CoroutineStats {
+        let inner = self.inner_exclusive_access();
+        let now = B::now_us();
+        let mut blocked_time_us = inner.blocked_time_us;
+        if inner.status == CoroutineStatus::Blocked {
+            blocked_time_us += now - inner.blocked_since_us;
+        }
+        CoroutineStats {
+            cid: self.cid,
+            schedule_count: inner.switch_count,
+            voluntary_switches: inner.voluntary_switches,
+            involuntary_switches: inner.involuntary_switches,
+            run_time_us: inner.run_time_at(now),
+            blocked_time_us,
+        }
+    }
+
+    /// 设置协程的名字，超出的部分被截断
+    pub fn set_name(&self, name: &[u8]) {
+        let mut inner = self.inner_exclusive_access();
+        let len = name.len().min(COROUTINE_NAME_LEN - 1);
+        inner.name = [0; COROUTINE_NAME_LEN];
+        inner.name[..len].copy_from_slice(&name[..len]);
+    }
+}
+
+impl<B> CoroutineInner<B> {
+    /// 开始计算占用CPU的时间
+    fn start_running(&mut self, now: usize) {
+        self.running_since_us = Some(now);
+    }
+
+    /// 停止计算占用CPU的时间，把这一段计入累计时间
+    fn stop_running(&mut self, now: usize) {
+        if let Some(since) = self.running_since_us.take() {
+            self.run_time_us += now - since;
+        }
+    }
+
+    /// 截至 `now` 占用CPU的累计时间
+    fn run_time_at(&self, now: usize) -> usize {
+        self.run_time_us + self.running_since_us.map_or(0, |since| now - since)
+    }
+}
+
+/// 以 `coroutine 7 (http-reader)` 的形式显示协程，没有名字时只显示ID
+impl<B: SwitchBackend> fmt::Display for CoroutineControlBlock<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "coroutine {}", self.cid)?;
+        let name = self.inner_exclusive_access().name;
+        let len = name.iter().position(|byte| *byte == 0).unwrap_or(COROUTINE_NAME_LEN);
+        if len > 0 {
+            let name = core::str::from_utf8(&name[..len]).unwrap_or("?");
+            write!(f, " ({})", name)?;
+        }
+        Ok(())
+    }
 }
 
 /// 协程管理器 - 每个任务有一个管理器来管理其协程
-pub struct CoroutineManager {
+pub struct CoroutineManager<B> {
     /// 所有协程控制块的列表
-    coroutines: Vec<Arc<CoroutineControlBlock>>,
+    coroutines: Vec<Arc<CoroutineControlBlock<B>>>,
     /// 当前运行的协程ID
     current_coroutine: Option<usize>,
     /// 就绪状态的协程队列
-    ready_queue: VecDeque<Arc<CoroutineControlBlock>>,
+    ready_queue: VecDeque<Arc<CoroutineControlBlock<B>>>,
+    /// 为true时就绪队列的队首必须下一个运行，它是被恢复的协程或者要处理上行调用的协程
+    front_runs_next: bool,
     /// 阻塞状态的协程队列
-    blocked_queue: Vec<Arc<CoroutineControlBlock>>,
+    blocked_queue: Vec<Arc<CoroutineControlBlock<B>>>,
+    /// 控制块仍被保留的未分离的已退出协程，按退出的先后排列
+    exited_cids: VecDeque<usize>,
     /// 下一个可用的栈基址
     next_stack_base: usize,
+    /// 下一个可分配的协程ID
+    next_cid: usize,
+    /// 协程中发生致命异常时的处理策略
+    fault_policy: FaultPolicy,
+    /// 主执行流退出进程时的处理策略
+    exit_policy: ExitPolicy,
+    /// 所有协程死锁时的处理策略
+    deadlock_policy: DeadlockPolicy,
+    /// 已退出协程留下的栈
+    stack_cache: Vec<CachedStack>,
+    /// 最多缓存的栈数量
+    stack_cache_limit: usize,
+    /// 用户态切换协程的代码，进程登记之前不使用调度页
+    fast_path: Option<FastPath>,
+    /// 协程阻塞和被唤醒时通知的用户态处理函数
+    upcall: Option<Upcall>,
+    /// 开启跟踪时为进程的pid
+    trace_pid: Option<usize>,
+    /// 跟踪到的、还没有交给内核跟踪缓冲区的记录
+    trace_records: Vec<TraceRecord>,
+}
+
+impl<B: SwitchBackend> Default for CoroutineManager<B> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl CoroutineManager {
+impl<B: SwitchBackend> CoroutineManager<B> {
     /// 创建一个新的协程管理器
     ///
+    /// 进程的主执行流被登记为ID为 [`MAIN_CID`] 的协程，并处于运行状态
+    ///
     /// # 返回值
     ///
     /// 返回一个初始化的协程管理器实例
     pub fn new() -> Self {
+        let main = Arc::new(CoroutineControlBlock::new(MAIN_CID, 0, 0, 0, 0));
+        let mut main_inner = main.inner_exclusive_access();
+        main_inner.status = CoroutineStatus::Running;
+        main_inner.switch_count = 1;
+        main_inner.start_running(B::now_us());
+        drop(main_inner);
         Self {
+            coroutines: alloc::vec![main],
+            current_coroutine: Some(MAIN_CID),
+            ready_queue: VecDeque::new(),
+            front_runs_next: false,
+            blocked_queue: Vec::new(),
+            exited_cids: VecDeque::new(),
+            next_stack_base: COROUTINE_STACK_REGION, // 从用户空间的某个区域开始分配栈空间
+            next_cid: MAIN_CID + 1,
+            fault_policy: FaultPolicy::KillProcess,
+            exit_policy: ExitPolicy::KillAll,
+            deadlock_policy: DeadlockPolicy::Report,
+            stack_cache: Vec::new(),
+            stack_cache_limit: DEFAULT_STACK_CACHE_LIMIT,
+            fast_path: None,
+            upcall: None,
+            trace_pid: None,
+            trace_records: Vec::new(),
+        }
+    }
+
+    /// 为fork出的子进程复制协程表
+    ///
+    /// 子进程的地址空间是父进程的完整副本，协程栈已经随之复制，这里只复制协程控制块。
+    /// 当前协程的寄存器在陷入上下文页中，也已经随地址空间复制。
+    /// 子进程没有子进程，等待子进程的协程在子进程中被唤醒，重新等待时会得到 -1
+    ///
+    /// # 参数
+    ///
+    /// * `mode` - 子进程继承协程的方式
+    ///
+    /// # 返回值
+    ///
+    /// 返回子进程的协程管理器，以及子进程中需要回收的协程栈基址
+    pub fn fork(&self, mode: ForkMode) -> (Self, Vec<usize>) {
+        let mut manager = Self {
             coroutines: Vec::new(),
-            current_coroutine: None,
+            current_coroutine: self.current_coroutine,
             ready_queue: VecDeque::new(),
+            front_runs_next: false,
             blocked_queue: Vec::new(),
-            next_stack_base: 0x8000_0000, // 从用户空间的某个区域开始分配栈空间
+            exited_cids: VecDeque::new(),
+            // 父进程分配过的栈地址在子进程中不再复用
+            next_stack_base: self.next_stack_base,
+            next_cid: self.next_cid,
+            fault_policy: self.fault_policy,
+            exit_policy: self.exit_policy,
+            deadlock_policy: self.deadlock_policy,
+            // 缓存的栈同样随地址空间复制到了子进程
+            stack_cache: self.stack_cache.clone(),
+            stack_cache_limit: self.stack_cache_limit,
+            // 子进程的代码和调度页都是父进程的副本
+            fast_path: self.fast_path,
+            upcall: None,
+            // 子进程不继承跟踪
+            trace_pid: None,
+            trace_records: Vec::new(),
+        };
+        let mut dropped_stacks = Vec::new();
+        match mode {
+            ForkMode::AllCoroutines => {
+                manager.coroutines = self
+                    .coroutines
+                    .iter()
+                    .map(|coroutine| Arc::new(coroutine.fork(coroutine.cid)))
+                    .collect();
+                // 协程在两个列表中的位置相同
+                let child_of = |coroutine: &Arc<CoroutineControlBlock<B>>| {
+                    let index = self
+                        .coroutines
+                        .iter()
+                        .position(|parent| Arc::ptr_eq(parent, coroutine))
+                        .unwrap();
+                    manager.coroutines[index].clone()
+                };
+                let ready_queue: VecDeque<_> = self.ready_queue.iter().map(child_of).collect();
+                let mut blocked_queue = Vec::new();
+                let mut woken = Vec::new();
+                for child in self.blocked_queue.iter().map(child_of) {
+                    let mut inner = child.inner_exclusive_access();
+                    if let Some(WaitReason::Child(_)) = inner.wait_reason {
+                        inner.status = CoroutineStatus::Ready;
+                        inner.wait_reason = None;
+                        inner.blocked_time_us += B::now_us() - inner.blocked_since_us;
+                        drop(inner);
+                        woken.push(child);
+                    } else {
+                        drop(inner);
+                        blocked_queue.push(child);
+                    }
+                }
+                // 父进程当前协程这一段的运行时间已经算在父进程里了
+                if let Some(current) = manager.current_coroutine() {
+                    current.inner_exclusive_access().start_running(B::now_us());
+                }
+                manager.ready_queue = ready_queue;
+                manager.ready_queue.extend(woken);
+                manager.front_runs_next = self.front_runs_next;
+                manager.blocked_queue = blocked_queue;
+                manager.exited_cids = self.exited_cids.clone();
+                // 运行处理函数的协程随其他协程一起复制，在子进程中被唤醒的协程还没有通知过
+                manager.upcall = self.upcall.clone();
+            }
+            ForkMode::CurrentCoroutine => {
+                let current = self.current_coroutine().unwrap();
+                let main = Arc::new(current.fork(MAIN_CID));
+                let mut inner = main.inner_exclusive_access();
+                inner.switch_count = 1;
+                inner.voluntary_switches = 0;
+                inner.involuntary_switches = 0;
+                inner.run_time_us = 0;
+                inner.blocked_time_us = 0;
+                inner.start_running(B::now_us());
+                drop(inner);
+                manager.coroutines.push(main);
+                manager.current_coroutine = Some(MAIN_CID);
+                for coroutine in self.coroutines.iter() {
+                    let inner = coroutine.inner_exclusive_access();
+                    // 已退出协程的栈已经回收，调用者自己的栈在子进程中继续使用
+                    if coroutine.cid != current.cid
+                        && inner.stack_size > 0
+                        && inner.status != CoroutineStatus::Exited
+                    {
+                        dropped_stacks.push(inner.stack_base);
+                    }
+                }
+            }
         }
+        (manager, dropped_stacks)
     }
 
     /// 创建新协程并添加到管理器中
     ///
+    /// 优先复用缓存中能容纳所需大小的最小的栈，否则分配新的栈地址范围。
+    /// 新栈的映射由调用者在进程地址空间中完成
+    ///
     /// # 参数
     ///
     /// * `entry` - 协程入口函数的地址
     /// * `arg` - 传递给协程函数的参数
-    /// * `stack_size` - 分配给协程的栈大小
+    /// * `stack_size` - 分配给协程的栈大小，必须是页大小的正整数倍，且不超过 `MAX_STACK_SIZE`
+    /// * `stack_free` - 检查新分配的栈地址范围 `[bottom, top)` 在进程地址空间中是否空闲
     ///
     /// # 返回值
     ///
-    /// 返回新创建的协程控制块的Arc引用
-    pub fn create_coroutine(&mut self, entry: usize, arg: usize, stack_size: usize) -> Arc<CoroutineControlBlock> {
-        let stack_base = self.next_stack_base;
-        // 更新下一个可用栈基址（避免栈空间重叠）
-        self.next_stack_base += stack_size;
+    /// 返回新创建的协程控制块的Arc引用，以及它的栈是否取自缓存；
+    /// 栈大小不合法，或者新栈的地址范围溢出或已被占用时返回None，管理器保持不变
+    pub fn create_coroutine(
+        &mut self,
+        entry: usize,
+        arg: usize,
+        stack_size: usize,
+        stack_free: impl FnOnce(usize, usize) -> bool,
+    ) -> Option<(Arc<CoroutineControlBlock<B>>, bool)> {
+        if stack_size == 0 || stack_size % PAGE_SIZE != 0 || stack_size > MAX_STACK_SIZE {
+            return None;
+        }
+        let cached = self
+            .stack_cache
+            .iter()
+            .enumerate()
+            .filter(|(_, stack)| stack.size >= stack_size)
+            .min_by_key(|(_, stack)| stack.size)
+            .map(|(index, _)| index);
+        let stack = match cached {
+            Some(index) => self.stack_cache.swap_remove(index),
+            None => {
+                let base = self.next_stack_base;
+                let top = base.checked_add(stack_size)?;
+                // 在两个栈之间留出一个保护页
+                let next_stack_base = top.checked_add(PAGE_SIZE)?;
+                if !stack_free(base, top) {
+                    return None;
+                }
+                self.next_stack_base = next_stack_base;
+                CachedStack {
+                    base,
+                    size: stack_size,
+                    mapped_base: top,
+                }
+            }
+        };
 
-        let coroutine = Arc::new(CoroutineControlBlock::new(entry, arg, stack_size, stack_base));
+        let cid = self.next_cid;
+        self.next_cid += 1;
+        let coroutine = CoroutineControlBlock::new(cid, entry, arg, stack.size, stack.base);
+        coroutine.inner_exclusive_access().stack_mapped_base = stack.mapped_base;
+        let coroutine = Arc::new(coroutine);
 
         // 将协程添加到列表和就绪队列
         self.coroutines.push(coroutine.clone());
         self.ready_queue.push_back(coroutine.clone());
-        coroutine
+        let creator = self.current_coroutine.unwrap_or(MAIN_CID);
+        self.trace(cid, TraceKind::Create, creator);
+        Some((coroutine, cached.is_some()))
+    }
+
+    /// 把已退出协程的栈放入缓存
+    ///
+    /// # 返回值
+    ///
+    /// 缓存已满或协程没有独立的栈时返回false，调用者应直接回收这个栈
+    pub fn cache_stack(&mut self, coroutine: &CoroutineControlBlock<B>) -> bool {
+        let inner = coroutine.inner_exclusive_access();
+        if inner.stack_size == 0 || self.stack_cache.len() >= self.stack_cache_limit {
+            return false;
+        }
+        self.stack_cache.push(CachedStack {
+            base: inner.stack_base,
+            size: inner.stack_size,
+            mapped_base: inner.stack_mapped_base,
+        });
+        true
+    }
+
+    /// 设置最多缓存的栈数量，超出的栈立即被移出缓存
+    ///
+    /// # 返回值
+    ///
+    /// 返回原来的上限，以及需要调用者回收的栈基址
+    pub fn set_stack_cache_limit(&mut self, limit: usize) -> (usize, Vec<usize>) {
+        let old_limit = core::mem::replace(&mut self.stack_cache_limit, limit);
+        (old_limit, self.trim_stack_cache(limit))
+    }
+
+    /// 只保留最多 `keep` 个缓存的栈
+    ///
+    /// # 返回值
+    ///
+    /// 返回被移出缓存、需要调用者回收的栈基址
+    pub fn trim_stack_cache(&mut self, keep: usize) -> Vec<usize> {
+        let keep = keep.min(self.stack_cache.len());
+        self.stack_cache
+            .drain(keep..)
+            .map(|stack| stack.base)
+            .collect()
+    }
+
+    /// 根据协程ID查找协程
+    pub fn get_coroutine(&self, cid: usize) -> Option<Arc<CoroutineControlBlock<B>>> {
+        self.coroutines
+            .iter()
+            .find(|coroutine| coroutine.cid == cid)
+            .cloned()
+    }
+
+    /// 获取当前运行的协程
+    pub fn current_coroutine(&self) -> Option<Arc<CoroutineControlBlock<B>>> {
+        self.current_coroutine.and_then(|cid| self.get_coroutine(cid))
+    }
+
+    /// 查找保留的栈地址范围内、尚未映射的部分包含 `addr` 的未退出协程
+    pub fn find_coroutine_by_stack(&self, addr: usize) -> Option<Arc<CoroutineControlBlock<B>>> {
+        self.coroutines
+            .iter()
+            .find(|coroutine| {
+                let inner = coroutine.inner_exclusive_access();
+                inner.status != CoroutineStatus::Exited
+                    && inner.stack_base <= addr
+                    && addr < inner.stack_mapped_base
+            })
+            .cloned()
+    }
+
+    /// 设置致命异常的处理策略，返回原来的策略
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) -> FaultPolicy {
+        core::mem::replace(&mut self.fault_policy, policy)
+    }
+
+    /// 当前协程发生致命异常时，如果只需终止这个协程，返回它的ID
+    pub fn fault_contained_coroutine(&self) -> Option<Arc<CoroutineControlBlock<B>>> {
+        match (self.fault_policy, self.current_coroutine) {
+            (FaultPolicy::KillCoroutine, Some(cid)) if cid != MAIN_CID => self.get_coroutine(cid),
+            _ => None,
+        }
+    }
+
+    /// 设置主执行流退出进程时的处理策略，返回原来的策略
+    pub fn set_exit_policy(&mut self, policy: ExitPolicy) -> ExitPolicy {
+        core::mem::replace(&mut self.exit_policy, policy)
+    }
+
+    /// 设置所有协程死锁时的处理策略，返回原来的策略
+    pub fn set_deadlock_policy(&mut self, policy: DeadlockPolicy) -> DeadlockPolicy {
+        core::mem::replace(&mut self.deadlock_policy, policy)
+    }
+
+    /// 所有协程死锁时的处理策略
+    pub fn deadlock_policy(&self) -> DeadlockPolicy {
+        self.deadlock_policy
+    }
+
+    /// 检查进程内的协程是否已经死锁
+    ///
+    /// 没有就绪的协程，且每个未退出的协程都在等待不可能发生的事件时即为死锁。
+    /// 此时进程内已经没有能运行的协程，等待通知的协程只可能被子进程唤醒；
+    /// 父进程的通知不计在内，等待父进程通知的协程在通知到来之前也会被报告
+    ///
+    /// # 参数
+    ///
+    /// * `has_child` - 进程是否还有与pid匹配、尚未退出的子进程，-1 匹配任意子进程
+    ///
+    /// # 返回值
+    ///
+    /// 死锁时返回所有阻塞的协程及它们等待的事件，否则返回None
+    pub fn find_deadlock(
+        &self,
+        has_child: impl Fn(isize) -> bool,
+    ) -> Option<Vec<(Arc<CoroutineControlBlock<B>>, WaitReason)>> {
+        if !self.ready_queue.is_empty() {
+            return None;
+        }
+        let mut waits = Vec::new();
+        for coroutine in self.coroutines.iter() {
+            let inner = coroutine.inner_exclusive_access();
+            match (inner.status, inner.wait_reason) {
+                (CoroutineStatus::Exited, _) | (CoroutineStatus::Blocked, Some(WaitReason::Upcall)) => {}
+                (CoroutineStatus::Blocked, Some(reason)) => {
+                    match reason {
+                        // 子进程退出时会唤醒等待它的协程
+                        WaitReason::Child(pid) if has_child(pid) => return None,
+                        // 子进程随时可能发来通知
+                        WaitReason::Notify if has_child(-1) => return None,
+                        _ => {}
+                    }
+                    waits.push((coroutine.clone(), reason));
+                }
+                _ => return None,
+            }
+        }
+        Some(waits)
+    }
+
+    /// 唤醒阻塞的主执行流并让它下一个运行，它的阻塞系统调用得到返回值 `ret`
+    ///
+    /// # 返回值
+    ///
+    /// 主执行流不是因为等待其他协程退出而阻塞时返回true
+    pub fn interrupt_main_wait(&mut self, ret: isize) -> bool {
+        let main = match self.get_coroutine(MAIN_CID) {
+            Some(main) => main,
+            None => return false,
+        };
+        let mut inner = main.inner_exclusive_access();
+        if inner.status != CoroutineStatus::Blocked
+            || inner.wait_reason == Some(WaitReason::Coroutines)
+        {
+            return false;
+        }
+        inner.wake_ret = Some(ret);
+        drop(inner);
+        self.try_resume_coroutine(MAIN_CID)
+    }
+
+    /// 是否还有未退出、未分离的协程（主执行流除外）
+    fn has_joinable_coroutine(&self) -> bool {
+        self.coroutines.iter().any(|coroutine| {
+            let inner = coroutine.inner_exclusive_access();
+            coroutine.cid != MAIN_CID && inner.status != CoroutineStatus::Exited && !inner.detached
+        })
+    }
+
+    /// 主执行流退出进程时，按退出策略决定是否先挂起等待其他协程
+    ///
+    /// # 返回值
+    ///
+    /// 如果主执行流已被挂起、需要切换到其他协程，返回true；如果可以立即退出进程，返回false
+    pub fn defer_main_exit(&mut self) -> bool {
+        if self.exit_policy != ExitPolicy::WaitAll
+            || self.current_coroutine != Some(MAIN_CID)
+            || !self.has_joinable_coroutine()
+        {
+            return false;
+        }
+        self.block_current_coroutine(WaitReason::Coroutines);
+        true
+    }
+
+    /// 最后一个未分离的协程退出或被分离后，唤醒等待退出进程的主执行流
+    fn wake_exit_waiter(&mut self) {
+        let waiting = self.blocked_queue.iter().any(|coroutine| {
+            coroutine.cid == MAIN_CID
+                && coroutine.inner_exclusive_access().wait_reason == Some(WaitReason::Coroutines)
+        });
+        if waiting && !self.has_joinable_coroutine() {
+            self.unblock_coroutine(MAIN_CID);
+        }
+    }
+
+    /// 分离协程，它退出后立即被回收，也不会推迟进程退出
+    ///
+    /// # 参数
+    ///
+    /// * `cid` - 要分离的协程ID
+    ///
+    /// # 返回值
+    ///
+    /// 成功返回true；协程不存在或是主执行流时返回false
+    pub fn detach_coroutine(&mut self, cid: usize) -> bool {
+        if cid == MAIN_CID {
+            return false;
+        }
+        let coroutine = match self.get_coroutine(cid) {
+            Some(coroutine) => coroutine,
+            None => return false,
+        };
+        coroutine.inner_exclusive_access().detached = true;
+        self.exited_cids.retain(|&exited| exited != cid);
+        self.reap_exited_coroutines();
+        self.wake_exit_waiter();
+        true
+    }
+
+    /// 回收已经退出的分离协程的控制块，未分离的已退出协程只保留最近退出的
+    /// [`EXITED_RETAIN_LIMIT`] 个
+    fn reap_exited_coroutines(&mut self) {
+        let current = self.current_coroutine;
+        let excess = self.exited_cids.len().saturating_sub(EXITED_RETAIN_LIMIT);
+        let reaped: Vec<usize> = self.exited_cids.drain(..excess).collect();
+        self.coroutines.retain(|coroutine| {
+            let inner = coroutine.inner_exclusive_access();
+            Some(coroutine.cid) == current
+                || inner.status != CoroutineStatus::Exited
+                || !(inner.detached || reaped.contains(&coroutine.cid))
+        });
+    }
+
+    /// 进程退出时释放所有协程控制块，协程栈随地址空间一起回收
+    pub fn release_all(&mut self) {
+        if let Some(cid) = self.current_coroutine {
+            self.trace(cid, TraceKind::SwitchOut, 0);
+        }
+        self.upcall = None;
+        self.coroutines.clear();
+        self.ready_queue.clear();
+        self.blocked_queue.clear();
+        self.exited_cids.clear();
+        self.stack_cache.clear();
+        self.current_coroutine = None;
+    }
+
+    /// 按创建顺序收集所有协程的统计信息
+    pub fn coroutine_infos(&self) -> Vec<CoroutineInfo> {
+        self.coroutines.iter().map(|coroutine| coroutine.info()).collect()
+    }
+
+    /// 开启或关闭跟踪
+    ///
+    /// # 参数
+    ///
+    /// * `pid` - 开启时为所属进程的pid，关闭时为None
+    ///
+    /// # 返回值
+    ///
+    /// 原来是否开启了跟踪
+    pub fn set_trace(&mut self, pid: Option<usize>) -> bool {
+        core::mem::replace(&mut self.trace_pid, pid).is_some()
+    }
+
+    /// 开启了跟踪时返回所属进程的pid
+    pub fn trace_pid(&self) -> Option<usize> {
+        self.trace_pid
+    }
+
+    /// 取走还没有交给内核跟踪缓冲区的记录
+    pub fn take_trace_records(&mut self) -> Vec<TraceRecord> {
+        core::mem::take(&mut self.trace_records)
+    }
+
+    /// 开启了跟踪时记录协程 `cid` 的一个事件
+    fn trace(&mut self, cid: usize, kind: TraceKind, arg: usize) {
+        if let Some(pid) = self.trace_pid {
+            self.trace_records.push(TraceRecord {
+                time_us: B::now_us(),
+                pid,
+                cid,
+                kind: kind as usize,
+                arg,
+            });
+        }
+    }
+
+    /// 登记用户态切换协程的代码，此后每次返回用户态前都要发布调度页
+    pub fn enable_fast_path(&mut self, fast_path: FastPath) {
+        self.fast_path = Some(fast_path);
+    }
+
+    /// 陷入内核时如果打断了用户态一次尚未生效的切换，让它从头重新执行
+    ///
+    /// # 参数
+    ///
+    /// * `sepc` - 陷入时的用户态pc
+    ///
+    /// # 返回值
+    ///
+    /// 返回用户态时应当继续执行的地址
+    pub fn restart_fast_switch(&self, sepc: usize) -> usize {
+        match self.fast_path {
+            Some(fast_path) if (fast_path.begin..fast_path.end).contains(&sepc) => fast_path.begin,
+            _ => sepc,
+        }
+    }
+
+    /// 陷入内核时取回用户态在调度页中完成的切换
+    ///
+    /// 上次发布之后，用户态可能已经轮转了就绪环并切换到了其中的协程。页中的协程集合
+    /// 与管理器不一致时说明页已被破坏，此时忽略页的内容，被破坏的只有这个进程自己
+    ///
+    /// # 参数
+    ///
+    /// * `page` - 当前任务的调度页
+    pub fn sync_from_sched_page(&mut self, page: &CoroutineSchedPage) {
+        if self.fast_path.is_none() || page.valid == 0 || page.ready_len != self.ready_queue.len() {
+            return;
+        }
+        let current = match self.current_coroutine() {
+            Some(current) => current,
+            None => return,
+        };
+        let new_cid = page.current_head >> 8;
+        let head = page.current_head & 0xff;
+        if head >= SCHED_READY_CAPACITY {
+            return;
+        }
+        let entries: Vec<SchedEntry> = (0..page.ready_len)
+            .map(|i| page.ready[(head + i) % SCHED_READY_CAPACITY])
+            .collect();
+        // 页中的当前协程加上就绪环，必须正好是管理器中的当前协程加上就绪队列
+        let mut page_cids: Vec<usize> = entries.iter().map(|entry| entry.cid).collect();
+        page_cids.push(new_cid);
+        page_cids.sort_unstable();
+        let mut cids: Vec<usize> = self.ready_queue.iter().map(|coroutine| coroutine.cid).collect();
+        cids.push(current.cid);
+        cids.sort_unstable();
+        if page_cids != cids {
+            return;
+        }
+
+        let mut ready_queue = VecDeque::new();
+        for entry in entries.iter() {
+            let coroutine = self.get_coroutine(entry.cid).unwrap();
+            let mut inner = coroutine.inner_exclusive_access();
+            inner.status = CoroutineStatus::Ready;
+            inner.user_cx = (entry.user_cx != 0).then_some(entry.user_cx);
+            drop(inner);
+            ready_queue.push_back(coroutine);
+        }
+        self.ready_queue = ready_queue;
+        if new_cid != current.cid {
+            // 两次陷入之间在用户态完成的多次切换只能合并成一次计入统计
+            let now = B::now_us();
+            let mut current_inner = current.inner_exclusive_access();
+            current_inner.voluntary_switches += 1;
+            current_inner.stop_running(now);
+            drop(current_inner);
+            let next = self.get_coroutine(new_cid).unwrap();
+            let mut next_inner = next.inner_exclusive_access();
+            next_inner.status = CoroutineStatus::Running;
+            next_inner.user_cx = None;
+            next_inner.switch_count += 1;
+            next_inner.start_running(now);
+            drop(next_inner);
+            self.current_coroutine = Some(new_cid);
+            self.trace(current.cid, TraceKind::SwitchOut, 0);
+            self.trace(new_cid, TraceKind::SwitchIn, 0);
+        }
+    }
+
+    /// 返回用户态前把当前协程和就绪队列写入调度页
+    ///
+    /// 就绪队列放不进就绪环（还要给切换出去的协程留一个位置）时，用户态只能经系统调用切换
+    ///
+    /// # 参数
+    ///
+    /// * `page` - 当前任务的调度页
+    pub fn publish_to_sched_page(&self, page: &mut CoroutineSchedPage) {
+        if self.fast_path.is_none() {
+            return;
+        }
+        let current = match self.current_coroutine {
+            Some(cid) if self.ready_queue.len() < SCHED_READY_CAPACITY => cid,
+            _ => {
+                page.valid = 0;
+                return;
+            }
+        };
+        for (entry, coroutine) in page.ready.iter_mut().zip(self.ready_queue.iter()) {
+            entry.cid = coroutine.cid;
+            entry.user_cx = coroutine.inner_exclusive_access().user_cx.unwrap_or(0);
+        }
+        page.ready_len = self.ready_queue.len();
+        page.current_head = current << 8;
+        page.valid = 1;
+    }
+
+    /// 修改已登记的上行调用处理函数的入口，为0时暂停通知并丢弃未处理的事件
+    ///
+    /// # 返回值
+    ///
+    /// 返回运行处理函数的协程ID；还没有登记过处理函数时返回None
+    pub fn set_upcall_entry(&mut self, entry: usize) -> Option<usize> {
+        let upcall = self.upcall.as_mut()?;
+        upcall.entry = entry;
+        if entry == 0 {
+            upcall.events.clear();
+        }
+        Some(upcall.cid)
+    }
 
+    /// 登记上行调用处理函数
+    ///
+    /// # 参数
+    ///
+    /// * `coroutine` - 刚创建的、用来运行处理函数的协程，它不再参与调度，只在有事件时被切换进来
+    /// * `entry` - 处理函数的入口地址
+    pub fn install_upcall(&mut self, coroutine: &Arc<CoroutineControlBlock<B>>, entry: usize) {
+        self.ready_queue.retain(|ready| ready.cid != coroutine.cid);
+        coroutine.set_name(b"upcall");
+        let mut inner = coroutine.inner_exclusive_access();
+        inner.status = CoroutineStatus::Blocked;
+        inner.wait_reason = Some(WaitReason::Upcall);
+        inner.blocked_since_us = B::now_us();
+        // 它不会自己退出，不能推迟进程退出
+        inner.detached = true;
+        drop(inner);
+        self.trace(coroutine.cid, TraceKind::Block, 0);
+        self.upcall = Some(Upcall {
+            cid: coroutine.cid,
+            entry,
+            events: VecDeque::new(),
+        });
+    }
+
+    /// 记录一个要交给上行调用处理函数的事件，处理函数空闲时让它下一个运行
+    fn post_upcall(&mut self, event: UpcallEvent, cid: usize) {
+        match self.upcall.as_mut() {
+            Some(upcall) if upcall.entry != 0 && upcall.cid != cid => {
+                upcall.events.push_back((event, cid));
+            }
+            _ => return,
+        }
+        self.deliver_upcall();
+    }
+
+    /// 处理函数空闲时把下一个事件交给它，并让它下一个运行
+    ///
+    /// 处理函数正在运行或已经就绪时什么也不做，事件留到它返回时再交给它
+    fn deliver_upcall(&mut self) {
+        let upcall = match self.upcall.as_mut() {
+            Some(upcall) => upcall,
+            None => return,
+        };
+        let coroutine = match self.coroutines.iter().find(|coroutine| coroutine.cid == upcall.cid) {
+            Some(coroutine) => coroutine.clone(),
+            None => return,
+        };
+        let mut inner = coroutine.inner_exclusive_access();
+        if inner.wait_reason != Some(WaitReason::Upcall) {
+            return;
+        }
+        let (event, cid) = match upcall.events.pop_front() {
+            Some(event) => event,
+            None => return,
+        };
+        inner.upcall_start = Some((upcall.entry, event, cid));
+        inner.status = CoroutineStatus::Ready;
+        inner.wait_reason = None;
+        inner.blocked_time_us += B::now_us() - inner.blocked_since_us;
+        drop(inner);
+        self.trace(coroutine.cid, TraceKind::Unblock, 0);
+        self.ready_queue.push_front(coroutine);
+        self.front_runs_next = true;
+    }
+
+    /// 上行调用处理函数处理完一个事件
+    ///
+    /// 还有事件时直接在陷入上下文中让处理函数从入口重新开始，否则让运行它的协程空闲下来
+    ///
+    /// # 参数
+    ///
+    /// * `trap_cx` - 当前任务的陷入上下文
+    ///
+    /// # 返回值
+    ///
+    /// 当前协程不是运行处理函数的协程时返回None；交给了下一个事件时返回Some(true)，
+    /// 此时陷入上下文的 a0 是事件；否则返回Some(false)，调用者应切换到其他协程
+    pub fn upcall_return(&mut self, trap_cx: &mut B) -> Option<bool> {
+        let upcall = self.upcall.as_mut()?;
+        if self.current_coroutine != Some(upcall.cid) {
+            return None;
+        }
+        let coroutine = self.coroutines.iter().find(|coroutine| coroutine.cid == upcall.cid)?;
+        let mut inner = coroutine.inner_exclusive_access();
+        if upcall.entry != 0 {
+            if let Some((event, cid)) = upcall.events.pop_front() {
+                trap_cx.set_pc(upcall.entry);
+                trap_cx.set_sp(inner.stack_base + inner.stack_size);
+                trap_cx.set_arg(0, event as usize);
+                trap_cx.set_arg(1, cid);
+                return Some(true);
+            }
+        }
+        inner.status = CoroutineStatus::Blocked;
+        inner.wait_reason = Some(WaitReason::Upcall);
+        inner.blocked_since_us = B::now_us();
+        drop(inner);
+        let cid = coroutine.cid;
+        self.trace(cid, TraceKind::Block, 0);
+        Some(false)
+    }
+
+    /// 是否存在可以切换过去的就绪协程
+    pub fn has_ready_coroutine(&self) -> bool {
+        !self.ready_queue.is_empty()
     }
 
     /// 切换到下一个就绪的协程
     ///
+    /// # 参数
+    ///
+    /// * `trap_cx` - 当前任务的陷入上下文
+    /// * `ret` - 当前协程之后恢复执行时在 a0 中得到的返回值
+    ///
     /// # 返回值
     ///
-    /// 如果成功切换，返回true，如果没有就绪的协程可切换，返回false
-    pub fn switch_to_next_coroutine(&mut self) -> bool {
+    /// 如果成功切换，返回下一个协程恢复执行时 a0 中应得到的值，
+    /// 如果没有就绪的协程可切换，返回None
+    pub fn switch_to_next_coroutine(&mut self, trap_cx: &mut B, ret: isize) -> Option<isize> {
         // 准备上下文切换所需的信息
-        if let Some(pair) = self.prepare_next_coroutine() {
-            let (current, next) = pair;
+        let (current, next) = self.prepare_next_coroutine()?;
 
-            // 执行上下文切换
-            self.perform_switch(&current, &next);
-            true
-        } else {
-            false
+        // 执行上下文切换
+        let next_ret = self.perform_switch(trap_cx, &current, &next, ret);
+
+        // 刚切换出去的协程如果已经退出且已分离，现在可以回收了
+        self.reap_exited_coroutines();
+        Some(next_ret)
+    }
+
+    /// 当前协程让出，切换到下一个就绪的协程
+    ///
+    /// # 参数
+    ///
+    /// * `trap_cx` - 当前任务的陷入上下文
+    /// * `user_cx` - 非0时当前协程已在用户态把寄存器保存在这里，之后由用户态的恢复例程恢复它，
+    ///   这样它以后也可以在用户态被切换过去
+    ///
+    /// # 返回值
+    ///
+    /// 如果成功切换，返回下一个协程恢复执行时 a0 中应得到的值，否则返回None
+    pub fn yield_current_coroutine(&mut self, trap_cx: &mut B, user_cx: usize) -> Option<isize> {
+        let current = self.current_coroutine()?;
+        let next_ret = self.switch_to_next_coroutine(trap_cx, 0)?;
+        if user_cx != 0 && self.fast_path.is_some() {
+            current.inner_exclusive_access().user_cx = Some(user_cx);
         }
+        Some(next_ret)
     }
 
     /// 准备下一个要切换的协程
@@ -176,90 +1332,145 @@ impl CoroutineManager {
     ///
     /// 如果有下一个就绪的协程，返回当前协程和下一个协程的引用对
     /// 如果没有就绪的协程，返回None
-    pub fn prepare_next_coroutine(&mut self) -> Option<(Arc<CoroutineControlBlock>, Arc<CoroutineControlBlock>)> {
+    #[allow(clippy::type_complexity)]
+    pub fn prepare_next_coroutine(&mut self) -> Option<(Arc<CoroutineControlBlock<B>>, Arc<CoroutineControlBlock<B>>)> {
         // 如果没有就绪的协程，返回None
         if self.ready_queue.is_empty() {
             return None;
         }
 
-        // 获取当前运行的协程
-        let current = if let Some(cid) = self.current_coroutine {
-            // 找到当前协程
-            let mut current_coroutine = None;
-            for coroutine in &self.coroutines {
-                if coroutine.cid == cid {
-                    current_coroutine = Some(coroutine.clone());
-                    break;
-                }
-            }
-
-            // 如果找到了当前协程，将其状态设为就绪并加入就绪队列
-            if let Some(coroutine) = &current_coroutine {
-                let mut inner = coroutine.inner_exclusive_access();
-                if inner.status == CoroutineStatus::Running {
-                    inner.status = CoroutineStatus::Ready;
-                    drop(inner);
-                    self.ready_queue.push_back(coroutine.clone());
-                }
-            }
+        // 获取当前运行的协程，它的上下文无论处于何种状态都需要保存
+        let current = self.current_coroutine()?;
 
-            current_coroutine
+        // 如果当前协程仍在运行，将其状态设为就绪并加入就绪队列
+        let mut inner = current.inner_exclusive_access();
+        if inner.status == CoroutineStatus::Running {
+            inner.status = CoroutineStatus::Ready;
+            drop(inner);
+            self.ready_queue.push_back(current.clone());
         } else {
-            None
-        };
-
-        // 从就绪队列取出下一个协程
-        if let Some(next_coroutine) = self.ready_queue.pop_front() {
-            let cid = next_coroutine.cid;
-            let mut inner = next_coroutine.inner_exclusive_access();
-            inner.status = CoroutineStatus::Running;
             drop(inner);
-
-            self.current_coroutine = Some(cid);
-
-            // 有当前协程，正常切换
-            if let Some(current) = current {
-                return Some((current, next_coroutine));
-            }
         }
 
-        // 没有当前协程或没有下一个协程
-        None
+        // 从就绪队列取出下一个协程：必须下一个运行的协程在队首，
+        // 其余情况下确定性调度由种子决定选哪一个，否则先进先出
+        let index = if core::mem::take(&mut self.front_runs_next) {
+            0
+        } else {
+            B::sched_random(self.ready_queue.len()).unwrap_or(0)
+        };
+        let next = self.ready_queue.remove(index)?;
+        next.inner_exclusive_access().status = CoroutineStatus::Running;
+        self.current_coroutine = Some(next.cid);
+
+        Some((current, next))
     }
 
     /// 执行协程上下文切换
     ///
+    /// 所有协程共用任务的陷入上下文页：先把页中的用户寄存器保存到当前协程，
+    /// 再把下一个协程保存的用户寄存器装入页中，内核相关的字段保持不变
+    ///
     /// # 参数
     ///
+    /// * `trap_cx` - 当前任务的陷入上下文
     /// * `current` - 当前协程的引用
     /// * `next` - 下一个协程的引用
-    pub fn perform_switch(&self, current: &Arc<CoroutineControlBlock>, next: &Arc<CoroutineControlBlock>) {
-        let current_ptr = &mut current.inner_exclusive_access().context as *mut TaskContext;
-        let next_ptr = &next.inner_exclusive_access().context as *const TaskContext;
+    /// * `ret` - 当前协程之后恢复执行时在 a0 中得到的返回值
+    ///
+    /// # 返回值
+    ///
+    /// 下一个协程恢复执行时 a0 中应得到的值
+    pub fn perform_switch(
+        &mut self,
+        trap_cx: &mut B,
+        current: &Arc<CoroutineControlBlock<B>>,
+        next: &Arc<CoroutineControlBlock<B>>,
+        ret: isize,
+    ) -> isize {
+        self.trace(current.cid, TraceKind::SwitchOut, 0);
+        self.trace(next.cid, TraceKind::SwitchIn, 0);
+        let now = B::now_us();
+        let mut current_inner = current.inner_exclusive_access();
+        current_inner.trap_cx = *trap_cx;
+        current_inner.trap_cx.set_arg(0, ret as usize);
+        current_inner.user_cx = None;
+        current_inner.voluntary_switches += 1;
+        current_inner.stop_running(now);
+        drop(current_inner);
 
-        // 使用系统提供的__switch函数进行上下文切换
-        unsafe {
-            __switch(current_ptr, next_ptr);
+        let mut next_inner = next.inner_exclusive_access();
+        if let Some(wake_ret) = next_inner.wake_ret.take() {
+            next_inner.trap_cx.set_arg(0, wake_ret as usize);
         }
+        if let Some((entry, event, cid)) = next_inner.upcall_start.take() {
+            // 处理函数每次都从入口开始，在自己的栈顶上运行
+            let stack_top = next_inner.stack_base + next_inner.stack_size;
+            next_inner.trap_cx.set_pc(entry);
+            next_inner.trap_cx.set_sp(stack_top);
+            next_inner.trap_cx.set_arg(0, event as usize);
+            next_inner.trap_cx.set_arg(1, cid);
+        }
+        if let (Some(user_cx), Some(fast_path)) = (next_inner.user_cx.take(), self.fast_path) {
+            // 协程是在用户态切换出去的，由用户态的恢复例程装入它保存的寄存器
+            next_inner.trap_cx.set_pc(fast_path.restore_entry);
+            next_inner.trap_cx.set_arg(0, user_cx);
+        }
+        next_inner.switch_count += 1;
+        next_inner.start_running(now);
+        trap_cx.load(&next_inner.trap_cx);
+        next_inner.trap_cx.arg(0) as isize
     }
 
-    /// 将当前运行的协程设置为阻塞状态
-    pub fn block_current_coroutine(&mut self) {
-        if let Some(cid) = self.current_coroutine {
-            for coroutine in &self.coroutines {
-                if coroutine.cid == cid {
-                    let mut inner = coroutine.inner_exclusive_access();
-                    inner.status = CoroutineStatus::Blocked;
-                    drop(inner);
-
-                    self.blocked_queue.push(coroutine.clone());
-                    self.current_coroutine = None;
-                    break;
-                }
+    /// 所属任务被时钟中断抢占时，记录当前协程的一次被动切换
+    pub fn preempt_current_coroutine(&mut self) {
+        if let Some(coroutine) = self.current_coroutine() {
+            let mut inner = coroutine.inner_exclusive_access();
+            if inner.status == CoroutineStatus::Running {
+                inner.involuntary_switches += 1;
             }
         }
     }
 
+    /// 所属任务被换下CPU时停止计算当前协程占用CPU的时间
+    pub fn pause_current_coroutine(&mut self) {
+        if let Some(coroutine) = self.current_coroutine() {
+            coroutine.inner_exclusive_access().stop_running(B::now_us());
+        }
+    }
+
+    /// 所属任务重新占用CPU时，如果当前协程仍在运行，继续计算它占用CPU的时间
+    pub fn continue_current_coroutine(&mut self) {
+        if let Some(coroutine) = self.current_coroutine() {
+            let mut inner = coroutine.inner_exclusive_access();
+            if inner.status == CoroutineStatus::Running {
+                inner.start_running(B::now_us());
+            }
+        }
+    }
+
+    /// 将当前运行的协程设置为阻塞状态
+    ///
+    /// 当前协程ID保持不变，直到下一次切换时保存完它的上下文
+    ///
+    /// # 参数
+    ///
+    /// * `reason` - 协程等待的事件
+    pub fn block_current_coroutine(&mut self, reason: WaitReason) {
+        if let Some(coroutine) = self.current_coroutine() {
+            let mut inner = coroutine.inner_exclusive_access();
+            inner.status = CoroutineStatus::Blocked;
+            inner.wait_reason = Some(reason);
+            inner.blocked_since_us = B::now_us();
+            drop(inner);
+
+            let cid = coroutine.cid;
+            self.blocked_queue.push(coroutine);
+            self.trace(cid, TraceKind::Block, 0);
+            self.post_upcall(UpcallEvent::Blocked, cid);
+        }
+    }
+
     /// 唤醒一个阻塞的协程，将其状态设为就绪
     ///
     /// # 参数
@@ -275,6 +1486,8 @@ impl CoroutineManager {
             if coroutine.cid == cid {
                 let mut inner = coroutine.inner_exclusive_access();
                 inner.status = CoroutineStatus::Ready;
+                inner.wait_reason = None;
+                inner.blocked_time_us += B::now_us() - inner.blocked_since_us;
                 drop(inner);
 
                 found_index = Some(i);
@@ -285,18 +1498,92 @@ impl CoroutineManager {
         if let Some(index) = found_index {
             let coroutine = self.blocked_queue.remove(index);
             self.ready_queue.push_back(coroutine);
+            self.trace(cid, TraceKind::Unblock, 0);
+            self.post_upcall(UpcallEvent::Unblocked, cid);
             true
         } else {
             false
         }
     }
+
+    /// 向协程发送通知
     ///
-    /// 就不写
-    /// 我就不写
-    pub fn try_resume_coroutine(&mut self, cid: usize) -> bool {
-        // 实现恢复协程的逻辑
-        // 只在此方法内部访问私有字段
+    /// 正在等待通知的协程被唤醒并得到 `value`，否则 `value` 被保存起来，协程下次等待时立即得到
+    ///
+    /// # 参数
+    ///
+    /// * `cid` - 接收通知的协程ID
+    /// * `value` - 通知携带的值
+    ///
+    /// # 返回值
+    ///
+    /// 唤醒了协程返回0，保存了通知返回1，协程不存在或已退出返回-1，已有未取走的通知返回-2
+    pub fn notify_coroutine(&mut self, cid: usize, value: usize) -> isize {
+        let coroutine = match self.get_coroutine(cid) {
+            Some(coroutine) => coroutine,
+            None => return -1,
+        };
+        let mut inner = coroutine.inner_exclusive_access();
+        if inner.status == CoroutineStatus::Exited {
+            return -1;
+        }
+        if inner.wait_reason == Some(WaitReason::Notify) {
+            inner.wake_ret = Some(value as isize);
+            drop(inner);
+            self.unblock_coroutine(cid);
+            return 0;
+        }
+        if inner.pending_notify.is_some() {
+            return -2;
+        }
+        inner.pending_notify = Some(value);
+        1
+    }
+
+    /// 取走当前协程未取走的通知
+    pub fn take_pending_notify(&mut self) -> Option<usize> {
+        self.current_coroutine()?.inner_exclusive_access().pending_notify.take()
+    }
 
+    /// 唤醒所有在等待某个子进程退出的协程
+    ///
+    /// # 参数
+    ///
+    /// * `pid` - 已经变为僵尸进程的子进程pid
+    ///
+    /// # 返回值
+    ///
+    /// 被唤醒的协程数量
+    pub fn wake_child_waiters(&mut self, pid: usize) -> usize {
+        let waiters: Vec<usize> = self
+            .blocked_queue
+            .iter()
+            .filter(|coroutine| {
+                match coroutine.inner_exclusive_access().wait_reason {
+                    Some(WaitReason::Child(wait_pid)) => wait_pid == -1 || wait_pid as usize == pid,
+                    _ => false,
+                }
+            })
+            .map(|coroutine| coroutine.cid)
+            .collect();
+        for cid in waiters.iter() {
+            self.unblock_coroutine(*cid);
+        }
+        waiters.len()
+    }
+
+    /// 让指定协程成为下一个被调度的协程
+    ///
+    /// 阻塞的协程会被强制唤醒，已就绪的协程会被移到就绪队列的最前面
+    ///
+    /// # 参数
+    ///
+    /// * `cid` - 要恢复的协程ID
+    ///
+    /// # 返回值
+    ///
+    /// 如果该协程可以被切换过去返回true，如果它正在运行、已退出或不存在返回false
+    pub fn try_resume_coroutine(&mut self, cid: usize) -> bool {
         // 检查是否是当前正在运行的协程
         if let Some(current_cid) = self.current_coroutine {
             if current_cid == cid {
@@ -305,21 +1592,49 @@ impl CoroutineManager {
             }
         }
 
-        // 检查是否在阻塞队列中
-        if self.unblock_coroutine(cid) {
-            // 已经成功从阻塞队列移到就绪队列
-            return true;
-        }
+        // 检查是否在阻塞队列中，成功后它位于就绪队列末尾
+        self.unblock_coroutine(cid);
 
-        // 检查是否已经在就绪队列中
-        for coroutine in &self.ready_queue {
-            if coroutine.cid == cid {
-                // 已经在就绪队列，无需操作
-                return true;
-            }
+        // 把它移到就绪队列的最前面，使下一次切换正好切换到它
+        if let Some(index) = self.ready_queue.iter().position(|coroutine| coroutine.cid == cid) {
+            let coroutine = self.ready_queue.remove(index).unwrap();
+            self.ready_queue.push_front(coroutine);
+            self.front_runs_next = true;
+            return true;
         }
 
         // 找不到协程或处于其他状态
         false
     }
-}
\ No newline at end of file
+
+    /// 将当前运行的协程标记为已退出
+    ///
+    /// 主执行流不能以协程的方式退出
+    ///
+    /// # 参数
+    ///
+    /// * `exit_code` - 协程的退出码
+    ///
+    /// # 返回值
+    ///
+    /// 返回已退出的协程，调用者负责回收它的栈；当前是主执行流时返回None
+    pub fn exit_current_coroutine(&mut self, exit_code: i32) -> Option<Arc<CoroutineControlBlock<B>>> {
+        let coroutine = self.current_coroutine()?;
+        if coroutine.cid == MAIN_CID {
+            return None;
+        }
+        let mut inner = coroutine.inner_exclusive_access();
+        inner.status = CoroutineStatus::Exited;
+        inner.exit_code = exit_code;
+        if !inner.detached {
+            self.exited_cids.push_back(coroutine.cid);
+        }
+        drop(inner);
+        self.trace(coroutine.cid, TraceKind::Exit, exit_code as isize as usize);
+        if self.upcall.as_ref().is_some_and(|upcall| upcall.cid == coroutine.cid) {
+            self.upcall = None;
+        }
+        self.wake_exit_waiter();
+        Some(coroutine)
+    }
+}
@@ -1,29 +1,94 @@
 //!Implementation of [`TaskManager`]
 use super::TaskControlBlock;
+use super::random::is_deterministic;
+use super::sched::{
+    DeadlineEntity, EdfScheduler, SchedEntity, SchedPolicy, Scheduler, SeededScheduler,
+};
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use alloc::boxed::Box;
 use alloc::sync::Arc;
 use lazy_static::*;
-///A array of `TaskControlBlock` that is thread-safe
+///The ready tasks, real-time ones by an [`EdfScheduler`] and the others by a [`Scheduler`] of the current policy
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    policy: SchedPolicy,
+    scheduler: Box<dyn Scheduler>,
+    rt: EdfScheduler,
 }
 
-/// A simple FIFO scheduler.
+/// The policy selected at build time, or a seeded random one with deterministic scheduling.
 impl TaskManager {
     ///Creat an empty TaskManager
     pub fn new() -> Self {
+        let policy = SchedPolicy::build_default();
+        let scheduler: Box<dyn Scheduler> = if is_deterministic() {
+            Box::new(SeededScheduler::new())
+        } else {
+            policy.scheduler(0)
+        };
         Self {
-            ready_queue: VecDeque::new(),
+            policy,
+            scheduler,
+            rt: EdfScheduler::new(),
         }
     }
     ///Add a task to `TaskManager`
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        if task.inner_exclusive_access().sched.deadline.is_some() {
+            self.rt.add(task);
+        } else {
+            self.scheduler.add(task);
+        }
     }
-    ///Remove the first task and return it,or `None` if `TaskManager` is empty
+    ///Remove the task the scheduler picks, real-time tasks first, and return it,or `None` if no task can run
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        self.rt.fetch().or_else(|| self.scheduler.fetch())
+    }
+    /// How long a task may run before the timer preempts it, see [`Scheduler::time_slice_us`]
+    pub fn time_slice_us(&self, entity: &SchedEntity) -> usize {
+        if entity.deadline.is_some() {
+            self.rt.time_slice_us(entity)
+        } else {
+            self.scheduler.time_slice_us(entity)
+        }
+    }
+    /// Whether the running task with scheduling state `entity` should be
+    /// preempted at `now_us`, because it used up its time slice or a
+    /// real-time task with an earlier deadline can run
+    pub fn should_preempt(&mut self, entity: &SchedEntity, now_us: usize) -> bool {
+        entity.running_us(now_us) >= self.time_slice_us(entity) || self.rt.preempts(entity, now_us)
+    }
+    /// When the timer should next interrupt the running task with scheduling
+    /// state `entity`: at the end of its time slice if another task is ready to
+    /// take the CPU, or of its budget if it is a real-time task, or when a
+    /// real-time task waiting for its period wakes up. `None` if nothing is due.
+    pub fn next_event_us(&mut self, entity: &SchedEntity, now_us: usize) -> Option<usize> {
+        let wakeup = self.rt.next_wakeup_us(now_us);
+        let contended = !self.scheduler.is_empty() || self.rt.earliest_deadline(now_us).is_some();
+        let slice_end = (contended || entity.deadline.is_some())
+            .then(|| entity.started_us + self.time_slice_us(entity));
+        [wakeup, slice_end].into_iter().flatten().min()
+    }
+    /// Replace the real-time parameters `old` of a task by `new`, see [`EdfScheduler::admit`]
+    pub fn admit_deadline(
+        &mut self,
+        old: Option<&DeadlineEntity>,
+        new: Option<&DeadlineEntity>,
+    ) -> bool {
+        self.rt.admit(old, new)
+    }
+    /// Name of the scheduler in use
+    pub fn scheduler_name(&self) -> &'static str {
+        self.scheduler.name()
+    }
+    /// Switch to a new scheduler of `policy`, moving the ready tasks over.
+    /// Return the previous policy.
+    pub fn set_policy(&mut self, policy: SchedPolicy, quantum_us: usize) -> SchedPolicy {
+        let mut scheduler = policy.scheduler(quantum_us);
+        for task in self.scheduler.drain() {
+            scheduler.add(task);
+        }
+        self.scheduler = scheduler;
+        core::mem::replace(&mut self.policy, policy)
     }
 }
 
@@ -39,3 +104,29 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+///Interface offered to ask whether the running task should be preempted
+pub fn should_preempt(entity: &SchedEntity, now_us: usize) -> bool {
+    TASK_MANAGER
+        .exclusive_access()
+        .should_preempt(entity, now_us)
+}
+///Interface offered to ask when the timer should next interrupt the running task
+pub fn next_event_us(entity: &SchedEntity, now_us: usize) -> Option<usize> {
+    TASK_MANAGER
+        .exclusive_access()
+        .next_event_us(entity, now_us)
+}
+///Interface offered to admit a task into the real-time class or release it
+pub fn admit_deadline(old: Option<&DeadlineEntity>, new: Option<&DeadlineEntity>) -> bool {
+    TASK_MANAGER.exclusive_access().admit_deadline(old, new)
+}
+///Interface offered to change the scheduling policy, returning the previous one
+pub fn set_sched_policy(policy: SchedPolicy, quantum_us: usize) -> SchedPolicy {
+    TASK_MANAGER
+        .exclusive_access()
+        .set_policy(policy, quantum_us)
+}
+///Interface offered to get the name of the scheduler in use
+pub fn scheduler_name() -> &'static str {
+    TASK_MANAGER.exclusive_access().scheduler_name()
+}
@@ -12,8 +12,21 @@
 //! A single global instance of [`PidAllocator`] called `PID_ALLOCATOR` allocates
 //! pid for user apps.
 //!
-//! Be careful when you see `__switch __switch_co` ASM function in `switch.S`. Control flow around this function
+//! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
+//!
+//! Every task owns a [`CoroutineManager`] in its TCB. Coroutines of a task share
+//! its kernel stack and trap context page; switching between them swaps the user
+//! registers saved in each [`CoroutineControlBlock`] in and out of that page.
+//! A task may also switch between ready coroutines in user space through its
+//! [`CoroutineSchedPage`]; the kernel takes those switches over on every trap
+//! and publishes the ready queue back to the page before returning.
+//!
+//! The coroutine events of a traced process are kept in its manager and moved
+//! into the kernel trace buffer in `trace.rs` whenever it returns to user space.
+//!
+//! Which ready task runs next, and for how long, is up to the [`Scheduler`] of
+//! the policy in use, see the `sched` module.
 mod context;
 mod manager;
 mod pid;
@@ -22,31 +35,48 @@ mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 mod coroutine;
+mod random;
+mod sched;
+mod trace;
 
-use crate::sync::UPSafeCell;
 use crate::loader::get_app_data_by_name;
+use crate::config::{COROUTINE_SCHED_PAGE, PAGE_SIZE};
+use crate::mm::{MapPermission, VirtAddr, translated_byte_buffer};
 use crate::sbi::shutdown;
+use crate::timer::{get_time_us, set_next_trigger};
+use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
+use log::debug;
 pub use manager::{TaskManager, fetch_task};
 use switch::__switch;
 
-use task::{TaskControlBlock, TaskStatus};
+use task::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
+pub use task::TaskTimes;
 
 pub use context::TaskContext;
-pub use manager::add_task;
+pub use manager::{add_task, admit_deadline, scheduler_name, set_sched_policy};
 pub use pid::{KernelStack, PidAllocator, PidHandle, pid_alloc};
 
-// 重要：使用TaskContext而非CoroutineContext来与系统保持一致
-// 从switch.S中引入协程切换函数
 pub use processor::{
     Processor, current_task, current_trap_cx, current_user_token, run_tasks, schedule,
     take_current_task,
 };
 // 从coroutine模块导出必要的类型
 pub use coroutine::{
-    CoroutineControlBlock, CoroutineStatus, CoroutineManager
+    COROUTINE_NAME_LEN, CoroutineInfo, CoroutineSchedPage, CoroutineStats,
+    DEADLOCK_EXIT_CODE, DeadlockPolicy, CoroutineStatus, ExitPolicy,
+    FastPath, FaultPolicy, ForkMode, SwitchBackend, TraceKind, TraceRecord, WaitReason, MAIN_CID
 };
+pub use random::{is_deterministic, sched_random, sched_seed, syscall_preempt_due};
+pub use sched::{DeadlineEntity, SchedEntity, SchedPolicy, Scheduler};
+pub use trace::read_trace;
+
+/// Coroutine control block whose user registers are saved as a [`TrapContext`]
+pub type CoroutineControlBlock = coroutine::CoroutineControlBlock<TrapContext>;
+/// Per-task coroutine manager switching coroutines through the trap context page
+pub type CoroutineManager = coroutine::CoroutineManager<TrapContext>;
 
 /// Suspend the current 'Running' task and run the next task in task list.
 pub fn suspend_current_and_run_next() {
@@ -58,6 +88,16 @@ pub fn suspend_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
+    let now = get_time_us();
+    task_inner.sched.switch_out(now);
+    task_inner.account_kernel_time(now);
+    if task_inner.sched.slice_expired {
+        task_inner.times.involuntary_switches += 1;
+    } else {
+        task_inner.times.voluntary_switches += 1;
+    }
+    // the current coroutine does not run while the task is off the CPU
+    task_inner.coroutine_manager.pause_current_coroutine();
     drop(task_inner);
     // ---- release current PCB
 
@@ -65,6 +105,94 @@ pub fn suspend_current_and_run_next() {
     add_task(task);
     // jump to scheduling cycle
     schedule(task_cx_ptr);
+    // back on the CPU
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .continue_current_coroutine();
+}
+
+/// Block the current 'Running' task, whose coroutines are all blocked, and run
+/// the next task in task list. It stays off the ready queue until
+/// [`wake_blocked_task`] puts it back.
+fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    let now = get_time_us();
+    task_inner.sched.switch_out(now);
+    task_inner.account_kernel_time(now);
+    task_inner.times.voluntary_switches += 1;
+    task_inner.coroutine_manager.pause_current_coroutine();
+    drop(task_inner);
+    // not pushed back to the ready queue
+    drop(task);
+    schedule(task_cx_ptr);
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .continue_current_coroutine();
+}
+
+/// Put `task` back to the ready queue if it is blocked and one of its
+/// coroutines has been woken
+fn wake_blocked_task(task: &Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked
+        || !task_inner.coroutine_manager.has_ready_coroutine()
+    {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task.clone());
+    // the running task may have had the CPU to itself, without a time slice
+    set_next_trigger();
+}
+
+/// Suspend the current 'Running' task whose time slice ran out and run the next task in task list.
+pub fn preempt_current_and_run_next() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.coroutine_manager.preempt_current_coroutine();
+    // the scheduler may treat the task differently from one that yielded
+    task_inner.sched.slice_expired = true;
+    drop(task_inner);
+    drop(task);
+    suspend_current_and_run_next();
+}
+
+/// Whether the current task has used up the time slice its scheduler gave it,
+/// or has to make room for a real-time task
+pub fn should_preempt_current() -> bool {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    manager::should_preempt(&task_inner.sched, get_time_us())
+}
+
+/// Charge the time since the current task returned to user space as user time,
+/// on every trap into the kernel
+pub fn account_trap_entry() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().account_user_time(get_time_us());
+}
+
+/// Charge the time since the current task entered the kernel or went on the
+/// CPU as kernel time, before it returns to user space
+pub fn account_trap_return() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().account_kernel_time(get_time_us());
+}
+
+/// When the scheduler next needs the timer while the current task runs, or
+/// `None` if it does not, see [`TaskManager::next_event_us`]
+pub fn next_sched_event_us() -> Option<usize> {
+    let task = current_task()?;
+    let task_inner = task.inner_exclusive_access();
+    manager::next_event_us(&task_inner.sched, get_time_us())
 }
 
 /// pid of usertests app in make run TEST=1
@@ -96,6 +224,12 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.task_status = TaskStatus::Zombie;
     // Record exit code
     inner.exit_code = exit_code;
+    // the times are final, for the parent to collect
+    inner.account_kernel_time(get_time_us());
+    // leave the utilization of a real-time task to others
+    if let Some(deadline) = inner.sched.deadline.take() {
+        admit_deadline(Some(&deadline), None);
+    }
     // do not move to its parent but under initproc
 
     // ++++++ access initproc TCB exclusively
@@ -104,11 +238,29 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         for child in inner.children.iter() {
             child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
             initproc_inner.children.push(child.clone());
+            if child.inner_exclusive_access().is_zombie() {
+                initproc_inner
+                    .coroutine_manager
+                    .wake_child_waiters(child.getpid());
+            }
         }
     }
     // ++++++ release parent PCB
+    wake_blocked_task(&INITPROC);
+
+    // wake up coroutines of the parent waiting for this child
+    if let Some(parent) = inner.parent.as_ref().and_then(|parent| parent.upgrade()) {
+        parent
+            .inner_exclusive_access()
+            .coroutine_manager
+            .wake_child_waiters(pid);
+        wake_blocked_task(&parent);
+    }
 
     inner.children.clear();
+    // release coroutines, their stacks go away with the user space
+    inner.coroutine_manager.release_all();
+    trace::flush_trace(&mut inner.coroutine_manager);
     // deallocate user space
     inner.memory_set.recycle_data_pages();
     drop(inner);
@@ -125,10 +277,6 @@ lazy_static! {
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
         get_app_data_by_name("initproc").unwrap()
     ));
-    /// 全局协程管理器，负责协程的创建、调度和状态管理
-    pub static ref COROUTINE_MANAGER: UPSafeCell<CoroutineManager> = unsafe {
-        UPSafeCell::new(CoroutineManager::new())
-    };
 }
 
 ///Add init process to the manager
@@ -136,70 +284,612 @@ pub fn add_initproc() {
     add_task(INITPROC.clone());
 }
 
-/// Create a new coroutine
+/// Find the live process `pid`, zombies not yet reaped included, by walking the
+/// process tree down from initproc
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    let mut tasks = Vec::new();
+    tasks.push(INITPROC.clone());
+    while let Some(task) = tasks.pop() {
+        if task.getpid() == pid {
+            return Some(task);
+        }
+        tasks.extend(task.inner_exclusive_access().children.iter().cloned());
+    }
+    None
+}
+
+
+/// Create a new coroutine in the current task
 ///
 /// # 参数
 ///
 /// * `entry` - 协程入口函数的地址
 /// * `arg` - 传递给协程函数的参数
-/// * `stack_size` - 协程栈大小
+/// * `stack_size` - 协程栈大小，必须是页大小的正整数倍
+///
+/// # 返回值
+///
+/// 返回新创建的协程控制块的引用；栈大小不合法或者找不到空闲的栈地址范围时返回None，
+/// 此时地址空间不受影响
+pub fn coroutine_create(
+    entry: usize,
+    arg: usize,
+    stack_size: usize,
+) -> Option<Arc<CoroutineControlBlock>> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let inner_ref = &mut *inner;
+    // 新栈必须位于调度页之下，且不能与进程已有的任何区域重叠
+    let (coroutine, reused) = inner_ref.coroutine_manager.create_coroutine(
+        entry,
+        arg,
+        stack_size,
+        |bottom, top| {
+            top <= COROUTINE_SCHED_PAGE && !inner_ref.memory_set.overlaps(bottom.into(), top.into())
+        },
+    )?;
+    let coroutine_inner = coroutine.inner_exclusive_access();
+    let stack_bottom = coroutine_inner.stack_base;
+    let stack_top = stack_bottom + coroutine_inner.stack_size;
+    let mapped_base = coroutine_inner.stack_mapped_base;
+    drop(coroutine_inner);
+    if reused {
+        // 复用的栈已经映射好了，只需重新填充，使栈使用量从头开始测量
+        paint_coroutine_stack(inner.get_user_token(), mapped_base, stack_top - mapped_base);
+        return Some(coroutine);
+    }
+    // 在进程地址空间中保留整个协程栈，但只映射最顶上的一页，其余部分在缺页时再映射
+    inner.memory_set.insert_lazy_framed_area(
+        stack_bottom.into(),
+        stack_top.into(),
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    grow_coroutine_stack(&mut inner, &coroutine, stack_top - PAGE_SIZE);
+    Some(coroutine)
+}
+
+/// Map the reserved coroutine stack page that `addr` falls into on a page fault
+///
+/// # 参数
+///
+/// * `addr` - 引发缺页异常的地址
 ///
 /// # 返回值
 ///
-/// 返回新创建的协程控制块的引用
-pub fn coroutine_create(entry: usize, arg: usize, stack_size: usize) -> Arc<CoroutineControlBlock> {
-    COROUTINE_MANAGER.exclusive_access().create_coroutine(entry, arg, stack_size)
+/// 如果 `addr` 位于当前任务某个协程保留但尚未映射的栈范围内并完成了映射，返回true，
+/// 此时重新执行引发异常的指令即可
+pub fn handle_coroutine_stack_fault(addr: usize) -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.coroutine_manager.find_coroutine_by_stack(addr) {
+        Some(coroutine) => {
+            let page_base: usize = VirtAddr::from(VirtAddr::from(addr).floor()).into();
+            grow_coroutine_stack(&mut inner, &coroutine, page_base)
+        }
+        None => false,
+    }
+}
+
+/// 把协程栈已映射的部分向下扩展到 `new_base`，并为新映射的页填充测量用的字节
+///
+/// 栈总是连续增长的，因此 `new_base` 和原来已映射部分之间的页也会一起被映射
+fn grow_coroutine_stack(
+    inner: &mut TaskControlBlockInner,
+    coroutine: &CoroutineControlBlock,
+    new_base: usize,
+) -> bool {
+    let mut coroutine_inner = coroutine.inner_exclusive_access();
+    let mapped_base = coroutine_inner.stack_mapped_base;
+    if new_base >= mapped_base {
+        return true;
+    }
+    if !inner.memory_set.map_pages_in_area(
+        VirtAddr::from(coroutine_inner.stack_base).into(),
+        new_base.into(),
+        mapped_base.into(),
+    ) {
+        return false;
+    }
+    coroutine_inner.stack_mapped_base = new_base;
+    paint_coroutine_stack(inner.get_user_token(), new_base, mapped_base - new_base);
+    true
 }
 
 /// Yield current coroutine to next ready coroutine
 ///
+/// # 参数
+///
+/// * `user_cx` - 非0时是当前协程在用户态保存寄存器的位置
+///
 /// # 返回值
 ///
-/// 如果成功切换到其他协程返回true，否则返回false
-pub fn coroutine_yield() -> bool {
-    let mut manager = COROUTINE_MANAGER.exclusive_access();
-    // 准备切换的协程对
-    if let Some((current, next)) = manager.prepare_next_coroutine() {
-        // 获取两个协程的上下文指针
-        let current_ctx_ptr = &mut current.inner_exclusive_access().context as *mut TaskContext;
-        let next_ctx_ptr = &next.inner_exclusive_access().context as *const TaskContext;
+/// 如果成功切换，返回切换到的协程恢复执行时得到的返回值，
+/// 否则返回-1
+pub fn coroutine_yield(user_cx: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    inner
+        .coroutine_manager
+        .yield_current_coroutine(trap_cx, user_cx)
+        .unwrap_or(-1)
+}
 
-        // 释放管理器锁，避免死锁
-        drop(manager);
+/// Resume a coroutine by its ID
+///
+/// # 参数
+///
+/// * `cid` - 要恢复的协程ID
+///
+/// # 返回值
+///
+/// 如果成功唤醒并切换到该协程，返回它恢复执行时得到的返回值，否则返回-1
+pub fn coroutine_resume(cid: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.coroutine_manager.try_resume_coroutine(cid) {
+        return -1;
+    }
+    let trap_cx = inner.get_trap_cx();
+    inner
+        .coroutine_manager
+        .switch_to_next_coroutine(trap_cx, 0)
+        .unwrap_or(-1)
+}
 
-        // 执行协程切换
-        unsafe {
-            __switch(current_ctx_ptr, next_ctx_ptr);
+/// Exit current coroutine and run the next ready one
+///
+/// # 参数
+///
+/// * `exit_code` - 协程的退出码
+///
+/// # 返回值
+///
+/// 切换到的协程恢复执行时得到的返回值；当前是主执行流时返回-1
+pub fn coroutine_exit(exit_code: i32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let coroutine = match inner.coroutine_manager.exit_current_coroutine(exit_code) {
+        Some(coroutine) => coroutine,
+        None => return -1,
+    };
+    // 协程已经不会再回到用户态，记录栈的使用量后就可以缓存或回收它的栈
+    let mut coroutine_inner = coroutine.inner_exclusive_access();
+    let stack_base = coroutine_inner.stack_base;
+    let stack_size = coroutine_inner.stack_size;
+    let high_watermark = coroutine_stack_usage(
+        inner.get_user_token(),
+        coroutine_inner.stack_mapped_base,
+        stack_base + stack_size,
+    );
+    coroutine_inner.stack_high_watermark = high_watermark;
+    drop(coroutine_inner);
+    debug!(
+        "[kernel] {} of process {} exited with code {}, stack high watermark {}/{} bytes",
+        coroutine,
+        task.getpid(),
+        exit_code,
+        high_watermark,
+        stack_size
+    );
+    if !inner.coroutine_manager.cache_stack(&coroutine) {
+        inner
+            .memory_set
+            .remove_area_with_start_vpn(VirtAddr::from(stack_base).into());
+    }
+    drop(inner);
+    drop(task);
+    coroutine_run_next(0)
+}
+
+/// Set how many stacks of exited coroutines the current task keeps for reuse
+///
+/// # 返回值
+///
+/// 返回原来的上限
+pub fn coroutine_set_stack_cache_limit(limit: usize) -> usize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let (old_limit, trimmed) = inner.coroutine_manager.set_stack_cache_limit(limit);
+    release_coroutine_stacks(&mut inner, &trimmed);
+    old_limit
+}
+
+/// Release cached coroutine stacks of the current task until at most `keep` remain
+///
+/// # 返回值
+///
+/// 返回被回收的栈的数量
+pub fn coroutine_trim_stack_cache(keep: usize) -> usize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trimmed = inner.coroutine_manager.trim_stack_cache(keep);
+    release_coroutine_stacks(&mut inner, &trimmed);
+    trimmed.len()
+}
+
+/// 从进程地址空间中移除这些协程栈，并释放它们占用的物理页
+fn release_coroutine_stacks(inner: &mut TaskControlBlockInner, stack_bases: &[usize]) {
+    for stack_base in stack_bases {
+        inner
+            .memory_set
+            .remove_area_with_start_vpn(VirtAddr::from(*stack_base).into());
+    }
+}
+
+/// Park the main flow instead of exiting if the exit policy waits for coroutines
+///
+/// 主执行流被唤醒时会重新执行 exit 系统调用，直到可以真正退出进程
+///
+/// # 返回值
+///
+/// 主执行流被挂起时返回切换到的协程恢复执行时得到的返回值；可以立即退出进程时返回None
+pub fn coroutine_defer_exit(exit_code: i32) -> Option<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner.coroutine_manager.defer_main_exit() {
+        return None;
+    }
+    // 回到 ecall 指令，恢复执行时 a0 中仍是退出码
+    inner.get_trap_cx().sepc -= 4;
+    drop(inner);
+    drop(task);
+    Some(coroutine_run_next(exit_code as isize))
+}
+
+/// Detach coroutine `cid` of the current task
+///
+/// # 返回值
+///
+/// 成功返回0，协程不存在或是主执行流时返回-1
+pub fn coroutine_detach(cid: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.coroutine_manager.detach_coroutine(cid) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Get the scheduling statistics of coroutine `cid` in the current task
+pub fn coroutine_stats(cid: usize) -> Option<CoroutineStats> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    inner
+        .coroutine_manager
+        .get_coroutine(cid)
+        .map(|coroutine| coroutine.stats())
+}
+
+/// Name coroutine `cid` of the current task for diagnostics
+///
+/// # 返回值
+///
+/// 成功返回0，协程不存在时返回-1
+pub fn coroutine_set_name(cid: usize, name: &[u8]) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    match inner.coroutine_manager.get_coroutine(cid) {
+        Some(coroutine) => {
+            coroutine.set_name(name);
+            0
         }
-        true
+        None => -1,
+    }
+}
+
+/// Get the coroutine to kill instead of the whole task after a fatal exception
+///
+/// # 返回值
+///
+/// 当前任务选择了 [`FaultPolicy::KillCoroutine`] 且异常发生在主执行流以外的协程中时，
+/// 返回该协程，调用者随后用 [`coroutine_exit`] 终止它；否则返回None
+pub fn fault_contained_coroutine() -> Option<Arc<CoroutineControlBlock>> {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .fault_contained_coroutine()
+}
+
+/// Get the deepest stack usage in bytes of coroutine `cid` in the current task
+///
+/// 运行中的协程现场测量，已退出的协程返回退出时记录的值
+///
+/// # 返回值
+///
+/// 栈的最大使用量；协程不存在或没有独立的栈（主执行流）时返回-1
+pub fn coroutine_stack_high_watermark(cid: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let coroutine = match inner.coroutine_manager.get_coroutine(cid) {
+        Some(coroutine) => coroutine,
+        None => return -1,
+    };
+    let coroutine_inner = coroutine.inner_exclusive_access();
+    if coroutine_inner.stack_size == 0 {
+        return -1;
+    }
+    if coroutine_inner.status == CoroutineStatus::Exited {
+        return coroutine_inner.stack_high_watermark as isize;
+    }
+    coroutine_stack_usage(
+        inner.get_user_token(),
+        coroutine_inner.stack_mapped_base,
+        coroutine_inner.stack_base + coroutine_inner.stack_size,
+    ) as isize
+}
+
+/// 刚映射的协程栈中填充的字节，用来测量栈的最大使用量
+const STACK_PAINT_BYTE: u8 = 0xcd;
+
+/// 用 [`STACK_PAINT_BYTE`] 填满协程栈
+fn paint_coroutine_stack(token: usize, stack_base: usize, stack_size: usize) {
+    for buffer in translated_byte_buffer(token, stack_base as *const u8, stack_size).unwrap() {
+        buffer.fill(STACK_PAINT_BYTE);
+    }
+}
+
+/// 从已映射部分的底部向上找到第一个被改写过的字节，估算协程栈的最大使用量
+///
+/// 尚未映射的页从未被访问过，不计入使用量
+fn coroutine_stack_usage(token: usize, mapped_base: usize, stack_top: usize) -> usize {
+    let mapped_size = stack_top - mapped_base;
+    let mut untouched = 0;
+    for buffer in translated_byte_buffer(token, mapped_base as *const u8, mapped_size).unwrap() {
+        match buffer.iter().position(|byte| *byte != STACK_PAINT_BYTE) {
+            Some(offset) => {
+                untouched += offset;
+                break;
+            }
+            None => untouched += buffer.len(),
+        }
+    }
+    mapped_size - untouched
+}
+
+/// 检查任务的协程是否已经死锁，返回所有阻塞的协程及它们等待的事件
+fn find_coroutine_deadlock(
+    inner: &TaskControlBlockInner,
+) -> Option<Vec<(Arc<CoroutineControlBlock>, WaitReason)>> {
+    inner.coroutine_manager.find_deadlock(|pid| {
+        inner
+            .children
+            .iter()
+            .any(|child| pid == -1 || child.getpid() == pid as usize)
+    })
+}
+
+/// Wait for a notification to the current coroutine
+///
+/// # 返回值
+///
+/// 已经有未取走的通知时立即返回它携带的值，否则阻塞直到收到通知，返回它携带的值
+pub fn coroutine_wait_notify() -> isize {
+    let pending = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .take_pending_notify();
+    match pending {
+        Some(value) => value as isize,
+        None => coroutine_block(WaitReason::Notify),
+    }
+}
+
+/// Notify coroutine `cid` of process `pid`, which must be the current process,
+/// its parent or one of its children
+///
+/// # 返回值
+///
+/// 唤醒了协程返回0，通知被保存返回1，进程或协程不存在、不允许通知或协程已退出返回-1，
+/// 协程已有未取走的通知返回-2
+pub fn coroutine_notify(pid: usize, cid: usize, value: usize) -> isize {
+    let task = current_task().unwrap();
+    let target = if pid == task.getpid() {
+        task
     } else {
-        false
+        let inner = task.inner_exclusive_access();
+        let child = inner.children.iter().find(|child| child.getpid() == pid).cloned();
+        let parent = inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .filter(|parent| parent.getpid() == pid);
+        match child.or(parent) {
+            Some(target) => target,
+            None => return -1,
+        }
+    };
+    let ret = target
+        .inner_exclusive_access()
+        .coroutine_manager
+        .notify_coroutine(cid, value);
+    wake_blocked_task(&target);
+    ret
+}
+
+/// Let user space of the current task switch coroutines through its scheduling page
+pub fn coroutine_enable_fast_path(fast_path: FastPath) {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .enable_fast_path(fast_path);
+}
+
+/// Take over the coroutine switches user space of the current task made through
+/// its scheduling page, on entry to the kernel
+///
+/// 陷入打断了一次尚未生效的切换时，让它返回用户态后从头重新执行
+pub fn coroutine_sync_from_user() {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    let sched_page = inner.get_sched_page();
+    trap_cx.sepc = inner.coroutine_manager.restart_fast_switch(trap_cx.sepc);
+    inner.coroutine_manager.sync_from_sched_page(sched_page);
+}
+
+/// Publish the coroutines of the current task to its scheduling page before
+/// returning to user space
+pub fn coroutine_publish_to_user() {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let sched_page = inner.get_sched_page();
+    // 浮点单元关闭时用户态切换协程不碰浮点寄存器，以免无谓地打开它
+    sched_page.fp_enabled = inner.get_trap_cx().fp_enabled() as usize;
+    inner.coroutine_manager.publish_to_sched_page(sched_page);
+}
+
+/// Move the trace records of the current task into the kernel trace buffer
+/// before returning to user space
+pub fn coroutine_flush_trace() {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    trace::flush_trace(&mut inner.coroutine_manager);
+}
+
+/// Turn coroutine tracing of the current task on or off, returning whether it was on
+pub fn coroutine_set_trace(enable: bool) -> bool {
+    let task = current_task().unwrap();
+    let pid = enable.then(|| task.getpid());
+    task.inner_exclusive_access().coroutine_manager.set_trace(pid)
+}
+
+/// Register the handler the current task is upcalled at when one of its coroutines
+/// blocks in the kernel or is woken up again
+///
+/// 第一次登记时创建一个运行处理函数的协程；之后只修改入口，`entry` 为0时暂停通知
+///
+/// # 参数
+///
+/// * `entry` - 处理函数的入口，事件在 a0 中，事件涉及的协程ID在 a1 中
+/// * `stack_size` - 第一次登记时处理函数的栈大小
+///
+/// # 返回值
+///
+/// 返回运行处理函数的协程ID；还没有登记过处理函数时不能暂停通知，返回-1；
+/// 栈大小不合法时同样返回-1
+pub fn coroutine_set_upcall(entry: usize, stack_size: usize) -> isize {
+    let registered = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .set_upcall_entry(entry);
+    if let Some(cid) = registered {
+        return cid as isize;
+    }
+    if entry == 0 {
+        return -1;
+    }
+    let coroutine = match coroutine_create(entry, 0, stack_size) {
+        Some(coroutine) => coroutine,
+        None => return -1,
+    };
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .install_upcall(&coroutine, entry);
+    coroutine.cid as isize
+}
+
+/// Return from the upcall handler of the current task
+///
+/// # 返回值
+///
+/// 还有事件时处理函数从入口重新开始，返回值是下一个事件；否则切换到其他协程，
+/// 返回切换到的协程恢复执行时得到的值；当前协程不在运行处理函数时返回-1
+pub fn coroutine_upcall_return() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    match inner.coroutine_manager.upcall_return(trap_cx) {
+        None => return -1,
+        Some(true) => return trap_cx.x[10] as isize,
+        Some(false) => {}
     }
+    drop(inner);
+    drop(task);
+    coroutine_run_next(0)
 }
 
-/// Block current coroutine
-pub fn coroutine_block() {
-    COROUTINE_MANAGER.exclusive_access().block_current_coroutine();
+/// Set what the current task does when all its coroutines deadlock
+///
+/// # 返回值
+///
+/// 返回原来的策略
+pub fn coroutine_set_deadlock_policy(policy: DeadlockPolicy) -> DeadlockPolicy {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .set_deadlock_policy(policy)
 }
 
-/// Resume a coroutine by its ID
+/// Block current coroutine until `reason` happens and run the next ready one
 ///
 /// # 参数
 ///
-/// * `cid` - 要恢复的协程ID
+/// * `reason` - 协程等待的事件
 ///
 /// # 返回值
 ///
-/// 如果成功唤醒并切换到该协程返回true，否则返回false
-pub fn coroutine_resume(cid: usize) -> bool {
-    let mut manager = COROUTINE_MANAGER.exclusive_access();
-    let success = manager.try_resume_coroutine(cid);
-    drop(manager);
+/// 当前协程被唤醒后得到的返回值（0，死锁时为 [`DEADLOCK_EXIT_CODE`]），
+/// 或切换到的协程恢复执行时得到的返回值
+pub fn coroutine_block(reason: WaitReason) -> isize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .coroutine_manager
+        .block_current_coroutine(reason);
+    coroutine_run_next(0)
+}
 
-    if success {
-        // 尝试切换到下一个就绪的协程
-        coroutine_yield()
-    } else {
-        false
+/// 切换到下一个就绪的协程
+///
+/// 如果进程内所有协程都在阻塞，就阻塞整个任务，不再参与调度，直到其他进程
+/// 退出或发来通知唤醒了其中的协程为止；
+/// 如果它们已经不可能被唤醒，按进程的死锁处理策略报告、唤醒主执行流或终止进程
+fn coroutine_run_next(ret: isize) -> isize {
+    let mut deadlock_handled = false;
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        let trap_cx = inner.get_trap_cx();
+        if let Some(next_ret) = inner.coroutine_manager.switch_to_next_coroutine(trap_cx, ret) {
+            return next_ret;
+        }
+        if !deadlock_handled {
+            if let Some(waits) = find_coroutine_deadlock(&inner) {
+                deadlock_handled = true;
+                println!(
+                    "[kernel] deadlock in process {}: every coroutine is blocked",
+                    task.getpid()
+                );
+                for (coroutine, reason) in waits.iter() {
+                    println!("[kernel]   {} is waiting for {}", coroutine, reason);
+                }
+                let policy = inner.coroutine_manager.deadlock_policy();
+                if policy == DeadlockPolicy::Error
+                    && inner
+                        .coroutine_manager
+                        .interrupt_main_wait(DEADLOCK_EXIT_CODE as isize)
+                {
+                    continue;
+                }
+                if policy != DeadlockPolicy::Report {
+                    drop(inner);
+                    drop(task);
+                    exit_current_and_run_next(DEADLOCK_EXIT_CODE);
+                    unreachable!();
+                }
+            }
+        }
+        drop(inner);
+        drop(task);
+        block_current_and_run_next();
     }
-}
\ No newline at end of file
+}
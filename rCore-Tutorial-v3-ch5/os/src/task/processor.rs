@@ -3,8 +3,9 @@ use super::__switch;
 use super::{TaskContext, TaskControlBlock};
 use super::{TaskStatus, fetch_task};
 use crate::sync::UPSafeCell;
+use crate::timer::{get_time_us, set_next_trigger};
 use crate::trap::TrapContext;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use lazy_static::*;
 ///Processor management structure
 pub struct Processor {
@@ -12,6 +13,8 @@ pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     ///The basic control flow of each core, helping to select and switch process
     idle_task_cx: TaskContext,
+    ///The task last switched in, whose FP registers the CPU may still hold
+    fp_owner: Weak<TaskControlBlock>,
 }
 
 impl Processor {
@@ -20,6 +23,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            fp_owner: Weak::new(),
         }
     }
     ///Get mutable reference to `idle_task_cx`
@@ -50,11 +54,22 @@ pub fn run_tasks() {
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
+            let now = get_time_us();
+            task_inner.sched.switch_in(now);
+            task_inner.times_since_us = now;
+            // the weak reference keeps the TCB address from being reused
+            let fp_owner = Arc::downgrade(&task);
+            if !Weak::ptr_eq(&processor.fp_owner, &fp_owner) {
+                task_inner.get_trap_cx().reload_fp();
+                processor.fp_owner = fp_owner;
+            }
             drop(task_inner);
             // release coming task TCB manually
             processor.current = Some(task);
             // release processor manually
             drop(processor);
+            // time the slice of the coming task
+            set_next_trigger();
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
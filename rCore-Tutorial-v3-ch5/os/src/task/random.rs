@@ -0,0 +1,111 @@
+//! Seeded scheduling decisions for replaying concurrency bugs
+//!
+//! Building the kernel with `SCHED_SEED=<number>` in the environment (e.g.
+//! `make run SCHED_SEED=42`) turns on deterministic scheduling. The next task
+//! in [`TaskManager::fetch`](super::manager::TaskManager) and the next ready
+//! coroutine of a process are then picked by a pseudo-random generator seeded
+//! with that number instead of by the scheduling policy. Tasks are preempted
+//! after a pseudo-random number of system calls rather than by the timer, which
+//! only remains as a slow watchdog for tasks that never make a system call; the
+//! Makefile runs QEMU with `-icount` so that even the watchdog fires at the
+//! same instruction every time. User space can not switch coroutines on its
+//! own in this mode, since the kernel could not choose the next one.
+//!
+//! Booting a kernel built with the same seed replays the same interleaving.
+
+use crate::sync::UPSafeCell;
+use lazy_static::*;
+
+/// Average number of system calls between two preemptions
+const PREEMPT_SYSCALLS: usize = 32;
+
+/// A xorshift64* generator
+struct SchedRng {
+    state: u64,
+}
+
+impl SchedRng {
+    fn new(seed: u64) -> Self {
+        // the state must not be zero
+        Self {
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..bound`, `bound` must not be zero
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// State of deterministic scheduling
+struct DeterministicSched {
+    rng: SchedRng,
+    /// system calls left before the current task is preempted
+    syscalls_left: usize,
+}
+
+impl DeterministicSched {
+    fn new(seed: u64) -> Self {
+        let mut sched = Self {
+            rng: SchedRng::new(seed),
+            syscalls_left: 0,
+        };
+        sched.syscalls_left = sched.next_slice();
+        sched
+    }
+
+    /// Number of system calls the next task may make before it is preempted
+    fn next_slice(&mut self) -> usize {
+        1 + self.rng.below(2 * PREEMPT_SYSCALLS)
+    }
+}
+
+lazy_static! {
+    /// `None` unless the kernel was built with `SCHED_SEED`
+    static ref DETERMINISTIC_SCHED: UPSafeCell<Option<DeterministicSched>> =
+        unsafe { UPSafeCell::new(sched_seed().map(DeterministicSched::new)) };
+}
+
+/// The seed the kernel was built with, if deterministic scheduling is on
+pub fn sched_seed() -> Option<u64> {
+    option_env!("SCHED_SEED")
+        .map(|seed| seed.parse().expect("SCHED_SEED should be a decimal number"))
+}
+
+/// Whether deterministic scheduling is on
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC_SCHED.exclusive_access().is_some()
+}
+
+/// Pick one of `bound` ready candidates, or `None` to take the first one in
+/// FIFO order because deterministic scheduling is off
+pub fn sched_random(bound: usize) -> Option<usize> {
+    DETERMINISTIC_SCHED
+        .exclusive_access()
+        .as_mut()
+        .map(|sched| sched.rng.below(bound.max(1)))
+}
+
+/// Count a system call of the current task; returns true when the task has
+/// used up its slice and should be preempted
+pub fn syscall_preempt_due() -> bool {
+    let mut sched = DETERMINISTIC_SCHED.exclusive_access();
+    let sched = match sched.as_mut() {
+        Some(sched) => sched,
+        None => return false,
+    };
+    sched.syscalls_left -= 1;
+    if sched.syscalls_left > 0 {
+        return false;
+    }
+    sched.syscalls_left = sched.next_slice();
+    true
+}
@@ -0,0 +1,82 @@
+//! Completely fair scheduling by virtual runtime
+use super::{SchedEntity, Scheduler};
+use crate::config::DEFAULT_PRIORITY;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Period in which every ready task should run once, in microseconds
+const SCHED_LATENCY_US: usize = 30_000;
+/// Shortest time slice, in microseconds
+const MIN_GRANULARITY_US: usize = 10_000;
+
+/// Run the ready task with the least virtual runtime, the CPU time it has used
+/// scaled by `DEFAULT_PRIORITY / priority`, so that tasks get CPU time in
+/// proportion to their priority.
+///
+/// The ready tasks share [`SCHED_LATENCY_US`] between them as their time
+/// slices. A task that comes back after a long absence (or from another
+/// policy) is placed at most half a latency behind the least virtual runtime
+/// seen so far, so that it can not monopolize the CPU to catch up.
+pub struct CfsScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// virtual runtime of the last task picked, never decreases
+    min_vruntime: usize,
+}
+
+impl CfsScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    /// Index of the ready task with the least virtual runtime, the earliest added one among equals
+    fn min_vruntime_index(&self) -> Option<usize> {
+        let mut min: Option<(usize, usize)> = None;
+        for (index, task) in self.ready_queue.iter().enumerate() {
+            let vruntime = task.inner_exclusive_access().sched.vruntime;
+            if min.is_none_or(|(_, min_vruntime)| vruntime < min_vruntime) {
+                min = Some((index, vruntime));
+            }
+        }
+        min.map(|(index, _)| index)
+    }
+}
+
+impl Scheduler for CfsScheduler {
+    fn name(&self) -> &'static str {
+        "cfs"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let entity = &mut inner.sched;
+        entity.vruntime += entity.last_ran_us * DEFAULT_PRIORITY / entity.priority;
+        entity.last_ran_us = 0;
+        entity.vruntime = entity
+            .vruntime
+            .max(self.min_vruntime.saturating_sub(SCHED_LATENCY_US / 2));
+        drop(inner);
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let index = self.min_vruntime_index()?;
+        let task = self.ready_queue.remove(index)?;
+        let vruntime = task.inner_exclusive_access().sched.vruntime;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+    fn time_slice_us(&self, _entity: &SchedEntity) -> usize {
+        // the running task is not in the queue
+        (SCHED_LATENCY_US / (self.ready_queue.len() + 1)).max(MIN_GRANULARITY_US)
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.drain(..).collect()
+    }
+}
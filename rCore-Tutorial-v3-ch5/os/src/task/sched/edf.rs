@@ -0,0 +1,244 @@
+//! Earliest deadline first, the real-time scheduling class
+use super::{SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Utilization is counted in millionths of the CPU
+const UTIL_SCALE: usize = 1_000_000;
+/// Highest total utilization of the real-time tasks, leaving the rest of the
+/// CPU to the normal tasks
+const UTIL_BOUND: usize = UTIL_SCALE * 9 / 10;
+/// Longest period, so that the release times and deadlines of the jobs cannot
+/// overflow
+pub const MAX_PERIOD_US: usize = 60_000_000;
+
+/// Real-time parameters and state of a task, in microseconds.
+///
+/// Every `period` the task releases a job that may use `runtime` of CPU time
+/// and should be done within `deadline`. A job is done when the task yields;
+/// the task then sleeps until its next period.
+#[derive(Clone)]
+pub struct DeadlineEntity {
+    /// CPU time of every job
+    pub runtime_us: usize,
+    /// interval between two jobs
+    pub period_us: usize,
+    /// time from the release of a job to its deadline
+    pub deadline_us: usize,
+    /// release time of the current job
+    pub period_start_us: usize,
+    /// CPU time the current job may still use
+    pub budget_us: usize,
+    /// the task yielded, the current job is done
+    pub job_done: bool,
+    /// number of jobs that missed their deadline
+    pub misses: usize,
+}
+
+impl DeadlineEntity {
+    /// A task whose first job is released at `now_us`, or `None` unless
+    /// `0 < runtime <= deadline <= period <= MAX_PERIOD_US`
+    pub fn new(
+        runtime_us: usize,
+        period_us: usize,
+        deadline_us: usize,
+        now_us: usize,
+    ) -> Option<Self> {
+        let valid = 0 < runtime_us
+            && runtime_us <= deadline_us
+            && deadline_us <= period_us
+            && period_us <= MAX_PERIOD_US;
+        valid.then_some(Self {
+            runtime_us,
+            period_us,
+            deadline_us,
+            period_start_us: now_us,
+            budget_us: runtime_us,
+            job_done: false,
+            misses: 0,
+        })
+    }
+
+    /// Share of the CPU the task may use, in millionths, `None` if it cannot be
+    /// computed
+    pub fn utilization(&self) -> Option<usize> {
+        self.runtime_us
+            .checked_mul(UTIL_SCALE)?
+            .checked_div(self.period_us)
+    }
+
+    /// Deadline of the current job
+    pub fn absolute_deadline(&self) -> usize {
+        self.period_start_us + self.deadline_us
+    }
+
+    /// Whether the current job may run
+    pub fn runnable(&self) -> bool {
+        !self.job_done && self.budget_us > 0
+    }
+
+    /// When the task can run again, if it is waiting for its next period
+    pub fn wakeup_us(&self) -> Option<usize> {
+        (!self.runnable()).then_some(self.period_start_us + self.period_us)
+    }
+
+    /// Check the current job at `now_us` and release the next one if it is due;
+    /// return how late the current job is if it missed its deadline.
+    ///
+    /// A job still unfinished at its deadline is given up, and the task waits
+    /// for its next period, so that one overrun does not make every later job
+    /// miss too.
+    pub fn refresh(&mut self, now_us: usize) -> Option<usize> {
+        let mut late = None;
+        if !self.job_done && now_us >= self.absolute_deadline() {
+            late = Some(now_us - self.absolute_deadline());
+            self.job_done = true;
+            self.misses += 1;
+        }
+        if !self.runnable() && now_us >= self.period_start_us + self.period_us {
+            // the job of the period containing `now_us`
+            let periods = (now_us - self.period_start_us) / self.period_us;
+            self.period_start_us += periods * self.period_us;
+            self.budget_us = self.runtime_us;
+            self.job_done = false;
+        }
+        late
+    }
+}
+
+/// Run the runnable real-time task with the earliest deadline.
+///
+/// Real-time tasks run ahead of all the normal tasks. A task that uses up the
+/// budget of its job is throttled until its next period, and the timer
+/// preempts the running task as soon as a real-time task with an earlier
+/// deadline becomes runnable.
+pub struct EdfScheduler {
+    /// the real-time tasks not on the CPU, both runnable and waiting for their period
+    tasks: Vec<Arc<TaskControlBlock>>,
+    /// total utilization of the admitted tasks, in millionths
+    utilization: usize,
+}
+
+impl EdfScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            utilization: 0,
+        }
+    }
+
+    /// Replace the parameters `old` of a task by `new`, either of which is
+    /// `None` for a normal task. Return false, changing nothing, if the total
+    /// utilization would exceed the bound.
+    pub fn admit(&mut self, old: Option<&DeadlineEntity>, new: Option<&DeadlineEntity>) -> bool {
+        let old = old.map_or(Some(0), DeadlineEntity::utilization);
+        let new = new.map_or(Some(0), DeadlineEntity::utilization);
+        let utilization = match (old, new) {
+            (Some(old), Some(new)) => (self.utilization - old).checked_add(new),
+            _ => None,
+        };
+        match utilization {
+            Some(utilization) if utilization <= UTIL_BOUND => {
+                self.utilization = utilization;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Release the next jobs due at `now_us`, reporting the missed deadlines
+    fn refresh(&mut self, now_us: usize) {
+        for task in self.tasks.iter() {
+            let mut inner = task.inner_exclusive_access();
+            let deadline = inner.sched.deadline.as_mut().unwrap();
+            if let Some(late) = deadline.refresh(now_us) {
+                let misses = deadline.misses;
+                drop(inner);
+                report_miss(task.getpid(), late, misses);
+            }
+        }
+    }
+
+    /// Earliest deadline of the runnable tasks at `now_us`
+    pub fn earliest_deadline(&mut self, now_us: usize) -> Option<usize> {
+        self.refresh(now_us);
+        self.tasks
+            .iter()
+            .filter_map(|task| {
+                let inner = task.inner_exclusive_access();
+                let deadline = inner.sched.deadline.as_ref().unwrap();
+                deadline.runnable().then(|| deadline.absolute_deadline())
+            })
+            .min()
+    }
+
+    /// Earliest time at or after `now_us` a waiting task can run again
+    pub fn next_wakeup_us(&mut self, now_us: usize) -> Option<usize> {
+        self.refresh(now_us);
+        self.tasks
+            .iter()
+            .filter_map(|task| {
+                let inner = task.inner_exclusive_access();
+                inner.sched.deadline.as_ref().unwrap().wakeup_us()
+            })
+            .min()
+    }
+
+    /// Whether the running task with scheduling state `entity` should make
+    /// room for a real-time task at `now_us`
+    pub fn preempts(&mut self, entity: &SchedEntity, now_us: usize) -> bool {
+        match self.earliest_deadline(now_us) {
+            None => false,
+            Some(earliest) => entity
+                .deadline
+                .as_ref()
+                .is_none_or(|deadline| earliest < deadline.absolute_deadline()),
+        }
+    }
+}
+
+/// Tell the console that a real-time task missed a deadline
+fn report_miss(pid: usize, late_us: usize, misses: usize) {
+    println!(
+        "[kernel] pid {} missed its deadline by {} us, {} misses so far",
+        pid, late_us, misses
+    );
+}
+
+impl Scheduler for EdfScheduler {
+    fn name(&self) -> &'static str {
+        "edf"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let ran = inner.sched.last_ran_us;
+        let deadline = inner.sched.deadline.as_mut().unwrap();
+        deadline.budget_us = deadline.budget_us.saturating_sub(ran);
+        drop(inner);
+        self.tasks.push(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let earliest = self.earliest_deadline(get_time_us())?;
+        let index = self.tasks.iter().position(|task| {
+            let inner = task.inner_exclusive_access();
+            let deadline = inner.sched.deadline.as_ref().unwrap();
+            deadline.runnable() && deadline.absolute_deadline() == earliest
+        })?;
+        Some(self.tasks.remove(index))
+    }
+    fn time_slice_us(&self, entity: &SchedEntity) -> usize {
+        entity
+            .deadline
+            .as_ref()
+            .map_or(0, |deadline| deadline.budget_us)
+    }
+    fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.tasks.drain(..).collect()
+    }
+}
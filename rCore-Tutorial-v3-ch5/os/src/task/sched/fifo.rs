@@ -0,0 +1,41 @@
+//! First come first served, the scheduler rCore started with
+use super::{DEFAULT_SLICE_US, SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Run the ready tasks in the order they became ready, each for [`DEFAULT_SLICE_US`]
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl FifoScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for FifoScheduler {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn time_slice_us(&self, _entity: &SchedEntity) -> usize {
+        DEFAULT_SLICE_US
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.drain(..).collect()
+    }
+}
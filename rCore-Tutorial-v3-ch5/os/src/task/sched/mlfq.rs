@@ -0,0 +1,91 @@
+//! Multi-level feedback queue
+use super::{SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use crate::timer::get_time_us;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Number of queues
+const LEVELS: usize = 3;
+/// Time slice of the highest level, doubled at every level below, in microseconds
+const BASE_SLICE_US: usize = 10_000;
+/// Interval between two boosts of all the tasks to the highest level, in microseconds
+const BOOST_INTERVAL_US: usize = 500_000;
+
+/// Run the ready task of the highest level first, round robin within a level.
+///
+/// A task that uses up its time slice drops one level and gets a slice twice
+/// as long there, while one that yields before stays where it is, so
+/// interactive tasks stay ahead of CPU-bound ones. Every [`BOOST_INTERVAL_US`]
+/// all the tasks go back to the highest level so that none starves.
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    /// number of boosts so far, a task whose `boost_epoch` is behind missed one while running
+    boost_epoch: usize,
+    last_boost_us: usize,
+}
+
+impl MlfqScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            boost_epoch: 0,
+            last_boost_us: get_time_us(),
+        }
+    }
+
+    /// Move all the ready tasks to the highest level
+    fn boost(&mut self) {
+        self.boost_epoch += 1;
+        for level in 1..LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                task.inner_exclusive_access().sched.level = 0;
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter() {
+            task.inner_exclusive_access().sched.boost_epoch = self.boost_epoch;
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let entity = &mut inner.sched;
+        if entity.boost_epoch != self.boost_epoch {
+            entity.boost_epoch = self.boost_epoch;
+            entity.level = 0;
+        } else if entity.slice_expired {
+            entity.level = (entity.level + 1).min(LEVELS - 1);
+        }
+        let level = entity.level;
+        drop(inner);
+        self.queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let now = get_time_us();
+        if now - self.last_boost_us >= BOOST_INTERVAL_US {
+            self.last_boost_us = now;
+            self.boost();
+        }
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn time_slice_us(&self, entity: &SchedEntity) -> usize {
+        BASE_SLICE_US << entity.level.min(LEVELS - 1)
+    }
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.queues
+            .iter_mut()
+            .flat_map(|queue| queue.drain(..))
+            .collect()
+    }
+}
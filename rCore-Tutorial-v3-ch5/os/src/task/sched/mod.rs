@@ -0,0 +1,195 @@
+//! Interchangeable process scheduling policies
+//!
+//! [`TaskManager`](super::TaskManager) keeps the ready tasks in a boxed
+//! [`Scheduler`], which decides which one runs next and how long it may run
+//! before the timer preempts it. The policy is chosen at build time with one of
+//! the `sched-fifo`, `sched-rr`, `sched-mlfq` or `sched-cfs` cargo features
+//! (`make run SCHED=rr`), stride scheduling being the default, and can be
+//! changed while the system runs with `sys_sched_setpolicy`, so that the same
+//! workloads can be timed under every policy.
+//!
+//! The timer is programmed for the next event of the running task only (see
+//! `set_next_trigger`): the end of its time slice, which is only armed while
+//! another task is ready, or the next period of a sleeping real-time task.
+//! A task running alone is not interrupted at all.
+//!
+//! The per-task state of all the policies lives in the [`SchedEntity`] of the
+//! TCB. The processor records in it when the task goes on the CPU, and how long
+//! it ran and why it left when it goes off, before handing it back to the
+//! scheduler.
+//!
+//! Tasks given a runtime, period and deadline with `sys_sched_set_deadline`
+//! form a real-time class above the policy, scheduled earliest deadline first
+//! by the [`EdfScheduler`].
+mod cfs;
+mod edf;
+mod fifo;
+mod mlfq;
+mod round_robin;
+mod seeded;
+mod stride;
+
+use super::TaskControlBlock;
+use crate::config::{BIG_STRIDE, DEFAULT_PRIORITY};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Time slice of the policies without one of their own, in microseconds
+pub const DEFAULT_SLICE_US: usize = 10_000;
+
+pub use cfs::CfsScheduler;
+pub use edf::{DeadlineEntity, EdfScheduler};
+pub use fifo::FifoScheduler;
+pub use mlfq::MlfqScheduler;
+pub use round_robin::RoundRobinScheduler;
+pub use seeded::SeededScheduler;
+pub use stride::StrideScheduler;
+
+/// A process scheduling policy
+pub trait Scheduler: Send {
+    /// Name of the policy, for messages
+    fn name(&self) -> &'static str;
+    /// Add a ready task
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Remove the task that runs next and return it, or `None` if no task is ready
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// How long a task with scheduling state `entity` may run before the timer
+    /// preempts it, in microseconds
+    fn time_slice_us(&self, entity: &SchedEntity) -> usize;
+    /// Whether no task is waiting in the scheduler
+    fn is_empty(&self) -> bool;
+    /// Remove all the ready tasks, to hand them to another scheduler
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>>;
+}
+
+/// Scheduling policies, numbered as in `sys_sched_setpolicy`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SchedPolicy {
+    /// First come first served with a fixed time slice
+    Fifo,
+    /// First come first served with a configurable time quantum
+    RoundRobin,
+    /// Multi-level feedback queue
+    Mlfq,
+    /// Completely fair scheduling by virtual runtime
+    Cfs,
+    /// Stride scheduling, CPU time in proportion to the priority
+    Stride,
+}
+
+impl SchedPolicy {
+    /// The policy selected with a `sched-*` cargo feature, stride scheduling if none is
+    pub fn build_default() -> Self {
+        if cfg!(feature = "sched-fifo") {
+            SchedPolicy::Fifo
+        } else if cfg!(feature = "sched-rr") {
+            SchedPolicy::RoundRobin
+        } else if cfg!(feature = "sched-mlfq") {
+            SchedPolicy::Mlfq
+        } else if cfg!(feature = "sched-cfs") {
+            SchedPolicy::Cfs
+        } else {
+            SchedPolicy::Stride
+        }
+    }
+
+    /// An empty scheduler of this policy; `quantum_us` is the time quantum of
+    /// round robin, 0 for its default
+    pub fn scheduler(self, quantum_us: usize) -> Box<dyn Scheduler> {
+        match self {
+            SchedPolicy::Fifo => Box::new(FifoScheduler::new()),
+            SchedPolicy::RoundRobin => Box::new(RoundRobinScheduler::new(quantum_us)),
+            SchedPolicy::Mlfq => Box::new(MlfqScheduler::new()),
+            SchedPolicy::Cfs => Box::new(CfsScheduler::new()),
+            SchedPolicy::Stride => Box::new(StrideScheduler::new()),
+        }
+    }
+}
+
+/// Scheduling state of a task, shared by all the policies
+#[derive(Clone)]
+pub struct SchedEntity {
+    /// set by `sys_set_priority`, the share of CPU time under stride and CFS
+    pub priority: usize,
+    /// how far `pass` advances each time the task is scheduled, `BIG_STRIDE / priority`
+    pub stride: usize,
+    /// the task with the smallest pass runs next under stride scheduling,
+    /// compared with [`pass_less`](stride::pass_less)
+    pub pass: usize,
+    /// CPU time weighted by the priority, the task with the least runs next under CFS
+    pub vruntime: usize,
+    /// queue of the task under MLFQ, 0 being the highest priority
+    pub level: usize,
+    /// the last MLFQ priority boost the task has taken part in
+    pub boost_epoch: usize,
+    /// when the task last went on the CPU, in microseconds
+    pub started_us: usize,
+    /// how long the task ran the last time it was on the CPU, in microseconds
+    pub last_ran_us: usize,
+    /// whether the task was preempted because its time slice ran out, rather
+    /// than giving up the CPU on its own, since it last went on the CPU
+    pub slice_expired: bool,
+    /// real-time parameters and state, `None` for a normal task
+    pub deadline: Option<DeadlineEntity>,
+}
+
+impl SchedEntity {
+    /// State of a task that never ran
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            stride: BIG_STRIDE / DEFAULT_PRIORITY,
+            pass: 0,
+            vruntime: 0,
+            level: 0,
+            boost_epoch: 0,
+            started_us: 0,
+            last_ran_us: 0,
+            slice_expired: false,
+            deadline: None,
+        }
+    }
+
+    /// State of a child forked from this task: it starts where the parent is,
+    /// so neither gets ahead, but at the highest MLFQ level like any new task.
+    /// A real-time task has a normal child, which was not admitted.
+    pub fn fork(&self) -> Self {
+        Self {
+            level: 0,
+            last_ran_us: 0,
+            slice_expired: false,
+            deadline: None,
+            ..self.clone()
+        }
+    }
+
+    /// Set the priority, at least 2
+    pub fn set_priority(&mut self, priority: usize) {
+        self.priority = priority;
+        self.stride = (BIG_STRIDE / priority).max(1);
+    }
+
+    /// The task yields; for a real-time task this ends the job of the current period
+    pub fn end_job(&mut self) {
+        if let Some(deadline) = self.deadline.as_mut() {
+            deadline.job_done = true;
+        }
+    }
+
+    /// The task goes on the CPU at `now_us`
+    pub fn switch_in(&mut self, now_us: usize) {
+        self.started_us = now_us;
+        self.slice_expired = false;
+    }
+
+    /// The task leaves the CPU at `now_us`
+    pub fn switch_out(&mut self, now_us: usize) {
+        self.last_ran_us = self.running_us(now_us);
+    }
+
+    /// How long the task has been on the CPU at `now_us`
+    pub fn running_us(&self, now_us: usize) -> usize {
+        now_us.saturating_sub(self.started_us)
+    }
+}
@@ -0,0 +1,53 @@
+//! Round robin with a configurable time quantum
+use super::{SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Time quantum when none is given, in microseconds
+const DEFAULT_QUANTUM_US: usize = 30_000;
+/// Shortest time quantum, so that the timer does not fire all the time
+const MIN_QUANTUM_US: usize = 1_000;
+
+/// Run the ready tasks in the order they became ready, each for one quantum
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    quantum_us: usize,
+}
+
+impl RoundRobinScheduler {
+    /// An empty scheduler with a quantum of `quantum_us`, or the default one if 0;
+    /// quanta shorter than [`MIN_QUANTUM_US`] are rounded up
+    pub fn new(quantum_us: usize) -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            quantum_us: if quantum_us == 0 {
+                DEFAULT_QUANTUM_US
+            } else {
+                quantum_us.max(MIN_QUANTUM_US)
+            },
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn time_slice_us(&self, _entity: &SchedEntity) -> usize {
+        self.quantum_us
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.drain(..).collect()
+    }
+}
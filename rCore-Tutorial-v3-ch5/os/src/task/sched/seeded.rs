@@ -0,0 +1,44 @@
+//! Seeded random picks for deterministic scheduling
+use super::{DEFAULT_SLICE_US, SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use crate::task::sched_random;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Run the ready task the `SCHED_SEED` generator picks, replacing the built-in
+/// policy when the kernel is built with deterministic scheduling
+pub struct SeededScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl SeededScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for SeededScheduler {
+    fn name(&self) -> &'static str {
+        "seeded"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let index = sched_random(self.ready_queue.len()).unwrap_or(0);
+        self.ready_queue.remove(index)
+    }
+    fn time_slice_us(&self, _entity: &SchedEntity) -> usize {
+        DEFAULT_SLICE_US
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.drain(..).collect()
+    }
+}
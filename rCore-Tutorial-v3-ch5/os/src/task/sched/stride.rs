@@ -0,0 +1,68 @@
+//! Stride scheduling
+use super::{DEFAULT_SLICE_US, SchedEntity, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Whether pass `a` is behind pass `b`.
+///
+/// Passes only grow and may wrap around, but the passes of all tasks stay within
+/// `BIG_STRIDE / 2` of each other since every stride is at most that much, so the
+/// wrapped difference tells which one is behind.
+pub fn pass_less(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+/// Run the ready task with the smallest pass and advance its pass by its
+/// stride, so that tasks get CPU time in proportion to their priority
+pub struct StrideScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl StrideScheduler {
+    /// An empty scheduler
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+
+    /// Index of the ready task with the smallest pass, the earliest added one among equals
+    fn min_pass_index(&self) -> Option<usize> {
+        let mut min: Option<(usize, usize)> = None;
+        for (index, task) in self.ready_queue.iter().enumerate() {
+            let pass = task.inner_exclusive_access().sched.pass;
+            if min.is_none_or(|(_, min_pass)| pass_less(pass, min_pass)) {
+                min = Some((index, pass));
+            }
+        }
+        min.map(|(index, _)| index)
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn name(&self) -> &'static str {
+        "stride"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let index = self.min_pass_index()?;
+        let task = self.ready_queue.remove(index)?;
+        let mut inner = task.inner_exclusive_access();
+        inner.sched.pass = inner.sched.pass.wrapping_add(inner.sched.stride);
+        drop(inner);
+        Some(task)
+    }
+    fn time_slice_us(&self, _entity: &SchedEntity) -> usize {
+        DEFAULT_SLICE_US
+    }
+    fn is_empty(&self) -> bool {
+        self.ready_queue.is_empty()
+    }
+    fn drain(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.drain(..).collect()
+    }
+}
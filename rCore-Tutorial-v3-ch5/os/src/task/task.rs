@@ -1,7 +1,10 @@
 //!Implementation of [`TaskControlBlock`]
 use super::TaskContext;
+use super::{CoroutineManager, CoroutineSchedPage, ForkMode};
 use super::{KernelStack, PidHandle, pid_alloc};
-use crate::config::TRAP_CONTEXT;
+use super::sched::SchedEntity;
+use super::trace::flush_trace;
+use crate::config::{COROUTINE_SCHED_PAGE, TRAP_CONTEXT};
 use crate::mm::{KERNEL_SPACE, MemorySet, PhysPageNum, VirtAddr};
 use crate::sync::UPSafeCell;
 use crate::trap::{TrapContext, trap_handler};
@@ -19,6 +22,7 @@ pub struct TaskControlBlock {
 
 pub struct TaskControlBlockInner {
     pub trap_cx_ppn: PhysPageNum,
+    pub sched_page_ppn: PhysPageNum,
     #[allow(unused)]
     pub base_size: usize,
     pub task_cx: TaskContext,
@@ -27,6 +31,13 @@ pub struct TaskControlBlockInner {
     pub parent: Option<Weak<TaskControlBlock>>,
     pub children: Vec<Arc<TaskControlBlock>>,
     pub exit_code: i32,
+    pub coroutine_manager: CoroutineManager,
+    /// state of the task in the scheduling policies
+    pub sched: SchedEntity,
+    /// CPU time and context switches of the task and its waited-for children
+    pub times: TaskTimes,
+    /// when the time since then was last charged to `times`, while the task is on the CPU
+    pub times_since_us: usize,
 }
 
 impl TaskControlBlockInner {
@@ -38,6 +49,9 @@ impl TaskControlBlockInner {
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
+    pub fn get_sched_page(&self) -> &'static mut CoroutineSchedPage {
+        self.sched_page_ppn.get_mut()
+    }
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
@@ -47,6 +61,17 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+    /// Charge the time since the last accounting point to user space, on a trap into the kernel
+    pub fn account_user_time(&mut self, now_us: usize) {
+        self.times.user_us += now_us - self.times_since_us;
+        self.times_since_us = now_us;
+    }
+    /// Charge the time since the last accounting point to the kernel, on a
+    /// return to user space or when the task leaves the CPU
+    pub fn account_kernel_time(&mut self, now_us: usize) {
+        self.times.kernel_us += now_us - self.times_since_us;
+        self.times_since_us = now_us;
+    }
 }
 
 impl TaskControlBlock {
@@ -60,6 +85,10 @@ impl TaskControlBlock {
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
+        let sched_page_ppn = memory_set
+            .translate(VirtAddr::from(COROUTINE_SCHED_PAGE).into())
+            .unwrap()
+            .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
         let kernel_stack = KernelStack::new(&pid_handle);
@@ -71,6 +100,7 @@ impl TaskControlBlock {
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     trap_cx_ppn,
+                    sched_page_ppn,
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
@@ -78,6 +108,10 @@ impl TaskControlBlock {
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
+                    coroutine_manager: CoroutineManager::new(),
+                    sched: SchedEntity::new(),
+                    times: TaskTimes::default(),
+                    times_since_us: 0,
                 })
             },
         };
@@ -99,6 +133,10 @@ impl TaskControlBlock {
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
+        let sched_page_ppn = memory_set
+            .translate(VirtAddr::from(COROUTINE_SCHED_PAGE).into())
+            .unwrap()
+            .ppn();
 
         // **** access inner exclusively
         let mut inner = self.inner_exclusive_access();
@@ -106,8 +144,15 @@ impl TaskControlBlock {
         inner.memory_set = memory_set;
         // update trap_cx ppn
         inner.trap_cx_ppn = trap_cx_ppn;
+        inner.sched_page_ppn = sched_page_ppn;
         // initialize base_size
         inner.base_size = user_sp;
+        // coroutines lived in the old address space, start over with the main flow only;
+        // tracing stays on so that a program can be traced from its first instruction
+        flush_trace(&mut inner.coroutine_manager);
+        let trace_pid = inner.coroutine_manager.trace_pid();
+        inner.coroutine_manager = CoroutineManager::new();
+        inner.coroutine_manager.set_trace(trace_pid);
         // initialize trap_cx
         let trap_cx = inner.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
@@ -119,15 +164,24 @@ impl TaskControlBlock {
         );
         // **** release inner automatically
     }
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    pub fn fork(self: &Arc<Self>, mode: ForkMode) -> Arc<Self> {
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        // copy user space(include trap context and coroutine stacks)
+        let mut memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        // copy coroutines, and free the stacks of those the child does not inherit
+        let (coroutine_manager, dropped_stacks) = parent_inner.coroutine_manager.fork(mode);
+        for stack_base in dropped_stacks {
+            memory_set.remove_area_with_start_vpn(VirtAddr::from(stack_base).into());
+        }
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
+        let sched_page_ppn = memory_set
+            .translate(VirtAddr::from(COROUTINE_SCHED_PAGE).into())
+            .unwrap()
+            .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
         let kernel_stack = KernelStack::new(&pid_handle);
@@ -138,6 +192,7 @@ impl TaskControlBlock {
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     trap_cx_ppn,
+                    sched_page_ppn,
                     base_size: parent_inner.base_size,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
@@ -145,6 +200,10 @@ impl TaskControlBlock {
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
+                    coroutine_manager,
+                    sched: parent_inner.sched.fork(),
+                    times: TaskTimes::default(),
+                    times_since_us: 0,
                 })
             },
         });
@@ -164,9 +223,44 @@ impl TaskControlBlock {
     }
 }
 
+/// CPU time of a task in microseconds and its context switches, as `sys_times` reports them
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TaskTimes {
+    /// time spent in user space
+    pub user_us: usize,
+    /// time spent in the kernel on behalf of the task
+    pub kernel_us: usize,
+    /// times the task gave up the CPU on its own
+    pub voluntary_switches: usize,
+    /// times the task was preempted
+    pub involuntary_switches: usize,
+    /// `user_us` of all the waited-for children, including theirs
+    pub children_user_us: usize,
+    /// `kernel_us` of all the waited-for children, including theirs
+    pub children_kernel_us: usize,
+    /// `voluntary_switches` of all the waited-for children, including theirs
+    pub children_voluntary_switches: usize,
+    /// `involuntary_switches` of all the waited-for children, including theirs
+    pub children_involuntary_switches: usize,
+}
+
+impl TaskTimes {
+    /// Add the times of a child that has been waited for
+    pub fn add_child(&mut self, child: &TaskTimes) {
+        self.children_user_us += child.user_us + child.children_user_us;
+        self.children_kernel_us += child.kernel_us + child.children_kernel_us;
+        self.children_voluntary_switches +=
+            child.voluntary_switches + child.children_voluntary_switches;
+        self.children_involuntary_switches +=
+            child.involuntary_switches + child.children_involuntary_switches;
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum TaskStatus {
     Ready,
     Running,
+    Blocked,
     Zombie,
 }
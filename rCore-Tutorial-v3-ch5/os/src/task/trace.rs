@@ -0,0 +1,82 @@
+//! Kernel ring buffer of coroutine trace records
+//!
+//! The coroutine manager of a traced process keeps its records until the
+//! process returns to user space, execs or exits; they are then moved into the
+//! single global [`TRACE_BUFFER`], from which `sys_trace_read` takes them.
+
+use super::{CoroutineManager, TraceKind, TraceRecord};
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_us;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Number of records the buffer holds before the oldest ones are overwritten
+pub const TRACE_BUFFER_LEN: usize = 2048;
+
+/// A fixed-size ring of trace records
+pub struct TraceBuffer {
+    /// records from the oldest to the newest
+    records: VecDeque<TraceRecord>,
+    /// records overwritten since they were last read
+    lost: usize,
+}
+
+impl TraceBuffer {
+    fn new() -> Self {
+        Self {
+            records: VecDeque::with_capacity(TRACE_BUFFER_LEN),
+            lost: 0,
+        }
+    }
+
+    /// Append a record, overwriting the oldest one when the buffer is full
+    fn push(&mut self, record: TraceRecord) {
+        if self.records.len() == TRACE_BUFFER_LEN {
+            self.records.pop_front();
+            self.lost += 1;
+        }
+        self.records.push_back(record);
+    }
+
+    /// Take at most `max` of the oldest records. If records were overwritten,
+    /// a [`TraceKind::Lost`] record telling how many comes first.
+    fn read(&mut self, max: usize) -> Vec<TraceRecord> {
+        let mut records = Vec::new();
+        if self.lost > 0 && max > 0 {
+            records.push(TraceRecord {
+                time_us: get_time_us(),
+                pid: 0,
+                cid: 0,
+                kind: TraceKind::Lost as usize,
+                arg: core::mem::take(&mut self.lost),
+            });
+        }
+        let count = (max - records.len()).min(self.records.len());
+        records.extend(self.records.drain(..count));
+        records
+    }
+}
+
+lazy_static! {
+    /// Trace records of all traced processes
+    pub static ref TRACE_BUFFER: UPSafeCell<TraceBuffer> =
+        unsafe { UPSafeCell::new(TraceBuffer::new()) };
+}
+
+/// Move the records collected by `manager` into the trace buffer
+pub fn flush_trace(manager: &mut CoroutineManager) {
+    let records = manager.take_trace_records();
+    if records.is_empty() {
+        return;
+    }
+    let mut buffer = TRACE_BUFFER.exclusive_access();
+    for record in records {
+        buffer.push(record);
+    }
+}
+
+/// Take at most `max` of the oldest records out of the trace buffer
+pub fn read_trace(max: usize) -> Vec<TraceRecord> {
+    TRACE_BUFFER.exclusive_access().read(max)
+}
@@ -2,10 +2,13 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::task::{is_deterministic, next_sched_event_us};
 use riscv::register::time;
 
-const TICKS_PER_SEC: usize = 100;
+/// with deterministic scheduling the timer is only a watchdog for tasks that never make a system call
+const WATCHDOG_TICKS_PER_SEC: usize = 1;
 const MSEC_PER_SEC: usize = 1000;
+const USEC_PER_SEC: usize = 1_000_000;
 ///get current time
 pub fn get_time() -> usize {
     time::read()
@@ -14,7 +17,20 @@ pub fn get_time() -> usize {
 pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
-/// set the next timer interrupt
+/// get current time in microseconds
+pub fn get_time_us() -> usize {
+    time::read() * USEC_PER_SEC / CLOCK_FREQ
+}
+/// set the next timer interrupt for the next scheduling event of the running
+/// task, turning the timer off if there is none so that a task running alone is
+/// never interrupted
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    if is_deterministic() {
+        set_timer(get_time() + CLOCK_FREQ / WATCHDOG_TICKS_PER_SEC);
+        return;
+    }
+    match next_sched_event_us() {
+        Some(time_us) => set_timer(time_us * CLOCK_FREQ / USEC_PER_SEC),
+        None => set_timer(usize::MAX),
+    }
 }
@@ -1,7 +1,10 @@
 //! Implementation of [`TrapContext`]
-use riscv::register::sstatus::{self, SPP, Sstatus};
+use crate::task::{SwitchBackend, sched_random};
+use crate::timer::get_time_us;
+use riscv::register::sstatus::{self, FS, SPP, Sstatus};
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 ///trap context structure containing sstatus, sepc and registers
 pub struct TrapContext {
     /// general regs[0..31]
@@ -16,6 +19,10 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// Addr of trap_handler function
     pub trap_handler: usize,
+    /// FP regs f[0..31], valid when the FPU is on in `sstatus`
+    pub f: [usize; 32],
+    /// CSR fcsr
+    pub fcsr: usize,
 }
 
 impl TrapContext {
@@ -34,6 +41,8 @@ impl TrapContext {
         let mut sstatus = sstatus::read();
         // set CPU privilege to User after trapping back
         sstatus.set_spp(SPP::User);
+        // the FPU stays off until the app first uses it, see `enable_fp`
+        sstatus.set_fs(FS::Off);
         let mut cx = Self {
             x: [0; 32],
             sstatus,
@@ -41,8 +50,65 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            f: [0; 32],
+            fcsr: 0,
         };
         cx.set_sp(sp);
         cx
     }
+    ///whether the FPU is on for this context
+    pub fn fp_enabled(&self) -> bool {
+        self.sstatus.fs() != FS::Off
+    }
+    ///turn the FPU on for this context with cleared FP regs
+    pub fn enable_fp(&mut self) {
+        self.sstatus.set_fs(FS::Initial);
+        self.f = [0; 32];
+        self.fcsr = 0;
+        self.reload_fp();
+    }
+    ///have `__restore` load the FP regs of this context, which the CPU does not
+    ///hold, by marking them Dirty
+    pub fn reload_fp(&mut self) {
+        if self.fp_enabled() {
+            self.sstatus.set_fs(FS::Dirty);
+        }
+    }
+}
+
+/// Coroutines of a task share its trap context page. A coroutine's saved
+/// context only carries the user registers, FP ones included; the kernel
+/// fields of the page are left untouched when it is switched in.
+impl SwitchBackend for TrapContext {
+    fn initial(entry: usize, stack_top: usize, arg: usize) -> Self {
+        let mut cx = Self::app_init_context(entry, stack_top, 0, 0, 0);
+        cx.x[10] = arg;
+        cx
+    }
+    fn load(&mut self, saved: &Self) {
+        self.x = saved.x;
+        self.sstatus = saved.sstatus;
+        self.sepc = saved.sepc;
+        self.f = saved.f;
+        self.fcsr = saved.fcsr;
+        self.reload_fp();
+    }
+    fn arg(&self, index: usize) -> usize {
+        self.x[10 + index]
+    }
+    fn set_arg(&mut self, index: usize, value: usize) {
+        self.x[10 + index] = value;
+    }
+    fn set_pc(&mut self, pc: usize) {
+        self.sepc = pc;
+    }
+    fn set_sp(&mut self, sp: usize) {
+        self.x[2] = sp;
+    }
+    fn now_us() -> usize {
+        get_time_us()
+    }
+    fn sched_random(bound: usize) -> Option<usize> {
+        sched_random(bound)
+    }
 }
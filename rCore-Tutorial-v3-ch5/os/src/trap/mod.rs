@@ -11,19 +11,37 @@
 //! It then calls different functionality based on what exactly the exception
 //! was. For example, timer interrupts trigger task preemption, and syscalls go
 //! to [`syscall()`].
+//!
+//! FP registers are handled lazily through `sstatus.FS`: an app starts with
+//! the FPU off, and its first FP instruction traps as an illegal instruction
+//! that turns it on. `__alltraps` saves the FP registers into the trap context
+//! only when they are dirty, and `__restore` loads them back only when the
+//! kernel marked them Dirty: after switching in another task or coroutine, or
+//! turning the FPU on. Otherwise the CPU still holds them, so a syscall or a
+//! trap costs no FP loads, and integer-only code pays nothing at all. The
+//! kernel itself never touches FP registers, which is why `__switch` does not
+//! save them.
+//! user_lib switches coroutines in user space the same way: it saves and
+//! restores the FP registers only once the scheduling page says the FPU is on.
 mod context;
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    account_trap_entry, account_trap_return, coroutine_exit, coroutine_flush_trace,
+    coroutine_publish_to_user, coroutine_sync_from_user, current_trap_cx,
+    current_user_token, exit_current_and_run_next, fault_contained_coroutine,
+    handle_coroutine_stack_fault, preempt_current_and_run_next, should_preempt_current,
+    syscall_preempt_due,
 };
 use crate::timer::set_next_trigger;
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie,
+    sstatus::FS,
+    stval, stvec,
 };
 
 global_asm!(include_str!("trap.S"));
@@ -54,8 +72,11 @@ pub fn enable_timer_interrupt() {
 /// handle an interrupt, exception, or system call from user space
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    account_trap_entry();
     let scause = scause::read();
     let stval = stval::read();
+    // user space may have switched coroutines without trapping since the last return
+    coroutine_sync_from_user();
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             // jump to next instruction anyway
@@ -66,6 +87,15 @@ pub fn trap_handler() -> ! {
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            // deterministic scheduling preempts on a system call count instead of the timer
+            if syscall_preempt_due() {
+                preempt_current_and_run_next();
+            }
+        }
+        Trap::Exception(Exception::StorePageFault) | Trap::Exception(Exception::LoadPageFault)
+            if handle_coroutine_stack_fault(stval) =>
+        {
+            // a coroutine stack grew into its reserved range, retry the instruction
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
@@ -73,23 +103,55 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            if let Some(coroutine) = fault_contained_coroutine() {
+                println!(
+                    "[kernel] {:?} in {}, bad addr = {:#x}, bad instruction = {:#x}, kernel killed the coroutine.",
+                    scause.cause(),
+                    coroutine,
+                    stval,
+                    current_trap_cx().sepc,
+                );
+                // page fault exit code, the next coroutine is in trap context now
+                coroutine_exit(-2);
+            } else {
+                println!(
+                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+                    scause.cause(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+                // page fault exit code
+                exit_current_and_run_next(-2);
+            }
+        }
+        Trap::Exception(Exception::IllegalInstruction) if current_trap_cx().sstatus.fs() == FS::Off => {
+            // first FP instruction of this context, turn the FPU on and retry it;
+            // any other illegal instruction traps again and is handled below
+            current_trap_cx().enable_fp();
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, kernel killed it.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            if let Some(coroutine) = fault_contained_coroutine() {
+                println!(
+                    "[kernel] IllegalInstruction in {}, bad instruction = {:#x}, kernel killed the coroutine.",
+                    coroutine,
+                    current_trap_cx().sepc,
+                );
+                // illegal instruction exit code
+                coroutine_exit(-3);
+            } else {
+                println!("[kernel] IllegalInstruction in application, kernel killed it.");
+                // illegal instruction exit code
+                exit_current_and_run_next(-3);
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            // the time slice or the budget of a real-time task ran out, or a
+            // real-time task woke up; otherwise wait for the next event
+            if should_preempt_current() {
+                preempt_current_and_run_next();
+            } else {
+                set_next_trigger();
+            }
         }
         _ => {
             panic!(
@@ -107,6 +169,9 @@ pub fn trap_handler() -> ! {
 /// set the reg a0 = trap_cx_ptr, reg a1 = phy addr of usr page table,
 /// finally, jump to new addr of __restore asm function
 pub fn trap_return() -> ! {
+    coroutine_publish_to_user();
+    coroutine_flush_trace();
+    account_trap_return();
     set_user_trap_entry();
     let trap_cx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
@@ -0,0 +1,41 @@
+#!/usr/bin/env python3
+"""Convert the TRACE lines printed by coroutine_trace into Chrome trace event JSON.
+
+Usage: python3 trace2json.py < console.log > trace.json
+Open the result in chrome://tracing or https://ui.perfetto.dev.
+Each process is shown as a process, each coroutine as a thread of it.
+"""
+
+import json
+import sys
+
+
+def main():
+    events = []
+    threads = set()
+    for line in sys.stdin:
+        fields = line.split()
+        if len(fields) != 6 or fields[0] != "TRACE":
+            continue
+        time_us, pid, cid, kind, arg = fields[1:]
+        event = {"name": kind, "ts": int(time_us), "pid": int(pid), "tid": int(cid)}
+        if kind == "switch_in":
+            event.update(name="running", ph="B")
+        elif kind == "switch_out":
+            event.update(name="running", ph="E")
+        else:
+            event.update(ph="i", s="t", args={"arg": int(arg)})
+        if kind != "lost":
+            threads.add((int(pid), int(cid)))
+        events.append(event)
+    for pid, cid in sorted(threads):
+        name = "main" if cid == 0 else "coroutine %d" % cid
+        events.append(
+            {"name": "thread_name", "ph": "M", "pid": pid, "tid": cid, "args": {"name": name}}
+        )
+    json.dump({"traceEvents": events, "displayTimeUnit": "ms"}, sys.stdout, indent=1)
+    sys.stdout.write("\n")
+
+
+if __name__ == "__main__":
+    main()
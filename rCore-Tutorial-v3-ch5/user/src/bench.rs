@@ -0,0 +1,40 @@
+// 延迟基准测试的计时与统计，供 bench_* 应用共用
+
+use crate::get_time_us;
+
+// 每项测量采集的样本数
+pub const SAMPLES: usize = 64;
+
+// 每个样本连续执行的操作数，样本取平均值，弥补微秒计时的精度不足
+pub const BATCH: usize = 16;
+
+// 开始计时，返回当前时间（微秒）
+pub fn start() -> usize {
+    get_time_us() as usize
+}
+
+// 从 start 开始执行了 ops 次操作，返回平均每次操作的耗时（纳秒）
+pub fn per_op_ns(start: usize, ops: usize) -> usize {
+    (get_time_us() as usize - start) * 1000 / ops
+}
+
+// 把纳秒数按微秒输出，保留三位小数
+struct Micros(usize);
+
+impl core::fmt::Display for Micros {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{:03} us", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+// 排序样本（纳秒），输出最小值、中位数和最大值
+pub fn report(name: &str, samples: &mut [usize]) {
+    samples.sort_unstable();
+    println!(
+        "{:<28} min {:>12}  median {:>12}  max {:>12}",
+        name,
+        Micros(samples[0]),
+        Micros(samples[samples.len() / 2]),
+        Micros(samples[samples.len() - 1])
+    );
+}
@@ -0,0 +1,170 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::bench::{BATCH, SAMPLES, per_op_ns, report, start};
+use user_lib::{
+    coroutine_create, coroutine_notify, coroutine_resume, coroutine_suspend,
+    coroutine_wait_notify, coroutine_yield, getpid, yield_,
+};
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn finish(_: usize) -> i32 {
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn wait_finished(count: usize) {
+    while FINISHED.load(Ordering::Relaxed) < count {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+}
+
+// 每个样本创建一批协程，计时之后再让它们全部运行结束，栈被缓存起来供下一批复用
+fn bench_create() {
+    let mut samples = [0; SAMPLES];
+    for sample in samples.iter_mut() {
+        FINISHED.store(0, Ordering::Relaxed);
+        let start = start();
+        for _ in 0..BATCH {
+            coroutine_create(finish, 0);
+        }
+        *sample = per_op_ns(start, BATCH);
+        wait_finished(BATCH);
+    }
+    report("coroutine create", &mut samples);
+}
+
+fn spinner(_: usize) -> i32 {
+    while !STOP.load(Ordering::Relaxed) {
+        coroutine_yield();
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+// 主执行流和另一个协程互相让出，每次让出往返包含两次切换
+fn bench_yield() {
+    STOP.store(false, Ordering::Relaxed);
+    FINISHED.store(0, Ordering::Relaxed);
+    coroutine_create(spinner, 0);
+    let mut samples = [0; SAMPLES];
+    for sample in samples.iter_mut() {
+        let start = start();
+        for _ in 0..BATCH {
+            coroutine_yield();
+        }
+        *sample = per_op_ns(start, 2 * BATCH);
+    }
+    report("coroutine yield (switch)", &mut samples);
+    STOP.store(true, Ordering::Relaxed);
+    wait_finished(1);
+}
+
+fn sleeper(_: usize) -> i32 {
+    while !STOP.load(Ordering::Relaxed) {
+        coroutine_suspend();
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+// 恢复一个挂起的协程，它立即再次挂起，回到主执行流
+fn bench_resume() {
+    STOP.store(false, Ordering::Relaxed);
+    FINISHED.store(0, Ordering::Relaxed);
+    let cid = coroutine_create(sleeper, 0);
+    coroutine_resume(cid);
+    let mut samples = [0; SAMPLES];
+    for sample in samples.iter_mut() {
+        let start = start();
+        for _ in 0..BATCH {
+            coroutine_resume(cid);
+        }
+        *sample = per_op_ns(start, BATCH);
+    }
+    report("coroutine resume+suspend", &mut samples);
+    STOP.store(true, Ordering::Relaxed);
+    coroutine_resume(cid);
+    wait_finished(1);
+}
+
+// 创建协程并立即切换过去，协程退出后回到主执行流
+fn bench_join() {
+    FINISHED.store(0, Ordering::Relaxed);
+    let mut samples = [0; SAMPLES];
+    for (index, sample) in samples.iter_mut().enumerate() {
+        let start = start();
+        for _ in 0..BATCH {
+            let cid = coroutine_create(finish, 0);
+            coroutine_resume(cid);
+        }
+        *sample = per_op_ns(start, BATCH);
+        assert_eq!(FINISHED.load(Ordering::Relaxed), (index + 1) * BATCH);
+    }
+    report("coroutine spawn+join", &mut samples);
+}
+
+static PONG: AtomicUsize = AtomicUsize::new(0);
+static PING_SAMPLES: [AtomicUsize; SAMPLES] = [const { AtomicUsize::new(0) }; SAMPLES];
+
+// 两个协程经内核的通知互相唤醒，每一轮包含两次阻塞和两次唤醒
+fn ping(_: usize) -> i32 {
+    let pid = getpid() as usize;
+    let pong = PONG.load(Ordering::Relaxed);
+    for sample in PING_SAMPLES.iter() {
+        let start = start();
+        for _ in 0..BATCH {
+            coroutine_notify(pid, pong, 0);
+            coroutine_wait_notify();
+        }
+        sample.store(per_op_ns(start, BATCH), Ordering::Relaxed);
+    }
+    coroutine_notify(pid, pong, 1);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn pong(ping: usize) -> i32 {
+    let pid = getpid() as usize;
+    while coroutine_wait_notify() == 0 {
+        coroutine_notify(pid, ping, 0);
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn bench_ping_pong() {
+    FINISHED.store(0, Ordering::Relaxed);
+    // pong 先运行并开始等待
+    let ping_cid = coroutine_create(ping, 0);
+    PONG.store(coroutine_create(pong, ping_cid), Ordering::Relaxed);
+    coroutine_resume(PONG.load(Ordering::Relaxed));
+    wait_finished(2);
+    let mut samples = [0; SAMPLES];
+    for (sample, value) in samples.iter_mut().zip(PING_SAMPLES.iter()) {
+        *sample = value.load(Ordering::Relaxed);
+    }
+    report("coroutine notify ping-pong", &mut samples);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    println!(
+        "bench_coroutine: {} samples of {} operations each",
+        SAMPLES, BATCH
+    );
+    bench_create();
+    bench_yield();
+    bench_resume();
+    bench_join();
+    bench_ping_pong();
+    0
+}
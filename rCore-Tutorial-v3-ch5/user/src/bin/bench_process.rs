@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::bench::{BATCH, SAMPLES, per_op_ns, report, start};
+use user_lib::{exit, fork, waitpid, yield_};
+
+// 与另一个进程互相让出；其他进程（如 initproc 和 shell）也可能在两次让出之间运行
+fn bench_yield() {
+    let pid = fork();
+    if pid == 0 {
+        // 比父进程多让出几次，保证父进程计时期间对方一直在
+        for _ in 0..(SAMPLES + 1) * BATCH {
+            yield_();
+        }
+        exit(0);
+    }
+    let mut samples = [0; SAMPLES];
+    for sample in samples.iter_mut() {
+        let start = start();
+        for _ in 0..BATCH {
+            yield_();
+        }
+        *sample = per_op_ns(start, 2 * BATCH);
+    }
+    report("process sys_yield (switch)", &mut samples);
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+}
+
+// fork 出立即退出的子进程并回收它
+fn bench_fork() {
+    let mut samples = [0; SAMPLES];
+    for sample in samples.iter_mut() {
+        let start = start();
+        for _ in 0..BATCH {
+            let pid = fork();
+            if pid == 0 {
+                exit(0);
+            }
+            let mut exit_code = 0;
+            assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        }
+        *sample = per_op_ns(start, BATCH);
+    }
+    report("process fork+exit+wait", &mut samples);
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    println!(
+        "bench_process: {} samples of {} operations each",
+        SAMPLES, BATCH
+    );
+    bench_yield();
+    bench_fork();
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    DEADLOCK_EXIT_CODE, DeadlockPolicy, coroutine_create, coroutine_resume,
+    coroutine_set_deadlock_policy, coroutine_set_name, coroutine_suspend, coroutine_waitpid,
+    coroutine_yield, exit, fork, sleep, waitpid,
+};
+
+// 挂起后只能被其他协程恢复
+fn sleeper(_: usize) -> i32 {
+    assert_eq!(coroutine_suspend(), 0);
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(
+        coroutine_set_deadlock_policy(DeadlockPolicy::Error),
+        DeadlockPolicy::Report
+    );
+    let cid = coroutine_create(sleeper, 0);
+    coroutine_set_name(cid, "sleeper");
+    coroutine_yield();
+
+    // waiting for a child that is still running is not a deadlock
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(coroutine_waitpid(pid as usize, &mut exit_code), pid);
+
+    // nothing can resume the main flow nor the sleeper
+    assert_eq!(coroutine_suspend(), DEADLOCK_EXIT_CODE as isize);
+    println!("main flow got a deadlock error");
+    coroutine_resume(cid);
+
+    // the same deadlock kills a process that asked for it
+    let pid = fork();
+    if pid == 0 {
+        coroutine_set_deadlock_policy(DeadlockPolicy::Kill);
+        let cid = coroutine_create(sleeper, 0);
+        coroutine_resume(cid);
+        coroutine_suspend();
+        unreachable!();
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, DEADLOCK_EXIT_CODE);
+    println!("coroutine_deadlock passed!");
+    0
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineInfo, ExitPolicy, coroutine_create, coroutine_detach, coroutine_info,
+    coroutine_set_exit_policy, coroutine_yield, exit, fork, waitpid,
+};
+
+const NUM_WORKERS: usize = 3;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+// 主执行流返回之后仍在运行的协程
+fn worker(rounds: usize) -> i32 {
+    for _ in 0..rounds {
+        coroutine_yield();
+    }
+    if FINISHED.fetch_add(1, Ordering::Relaxed) + 1 == NUM_WORKERS {
+        println!("coroutine_exit_policy passed!");
+    }
+    0
+}
+
+// 永不退出的协程，只有分离后进程才能退出
+fn forever(_: usize) -> i32 {
+    loop {
+        coroutine_yield();
+    }
+}
+
+fn count_coroutines() -> usize {
+    let mut infos = [CoroutineInfo::default(); 8];
+    coroutine_info(-1, &mut infos) as usize
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // the default policy kills live coroutines together with the process
+    let pid = fork();
+    if pid == 0 {
+        coroutine_create(forever, 0);
+        exit(3);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+
+    // a detached coroutine is reclaimed as soon as it exits
+    let total = count_coroutines();
+    let cid = coroutine_create(worker, 0);
+    assert_eq!(coroutine_detach(cid), 0);
+    assert_eq!(coroutine_detach(0), -1);
+    coroutine_yield();
+    assert_eq!(count_coroutines(), total);
+    FINISHED.store(0, Ordering::Relaxed);
+
+    // returning from main waits for the workers, but not for the detached one
+    assert_eq!(coroutine_set_exit_policy(ExitPolicy::WaitAll), ExitPolicy::KillAll);
+    for i in 0..NUM_WORKERS {
+        coroutine_create(worker, (i + 1) * 10);
+    }
+    let cid = coroutine_create(forever, 0);
+    coroutine_detach(cid);
+    0
+}
@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{coroutine_create, coroutine_resume, coroutine_yield, fork, get_time, waitpid, yield_};
+
+const WORKERS: usize = 3;
+const ROUNDS: usize = 2000;
+
+static LOG: [AtomicUsize; WORKERS * ROUNDS] = [const { AtomicUsize::new(0) }; WORKERS * ROUNDS];
+static LOG_LEN: AtomicUsize = AtomicUsize::new(0);
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+// 每轮记下自己的编号后让出，就绪队列先进先出，所以记录应当严格按 1, 2, 3 轮转
+fn worker(id: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        let index = LOG_LEN.fetch_add(1, Ordering::Relaxed);
+        LOG[index].store(id, Ordering::Relaxed);
+        coroutine_yield();
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn run_round_robin() {
+    LOG_LEN.store(0, Ordering::Relaxed);
+    FINISHED.store(0, Ordering::Relaxed);
+    for id in 1..=WORKERS {
+        coroutine_create(worker, id);
+    }
+    let start = get_time();
+    while FINISHED.load(Ordering::Relaxed) < WORKERS {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+    let elapsed = get_time() - start;
+    assert_eq!(LOG_LEN.load(Ordering::Relaxed), WORKERS * ROUNDS);
+    for (index, id) in LOG.iter().enumerate() {
+        assert_eq!(id.load(Ordering::Relaxed), index % WORKERS + 1);
+    }
+    println!(
+        "{} coroutines yielded {} times each in {} ms",
+        WORKERS, ROUNDS, elapsed
+    );
+}
+
+static STEPS: [AtomicUsize; 2] = [const { AtomicUsize::new(0) }; 2];
+static STOP: AtomicBool = AtomicBool::new(false);
+
+// 两个协程互相让出时在用户态切换，主执行流再经内核恢复其中在用户态切换出去的那个
+fn stepper(index: usize) -> i32 {
+    while !STOP.load(Ordering::Relaxed) {
+        STEPS[index].fetch_add(1, Ordering::Relaxed);
+        coroutine_yield();
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn run_resume() {
+    FINISHED.store(0, Ordering::Relaxed);
+    let cids = [coroutine_create(stepper, 0), coroutine_create(stepper, 1)];
+    for round in 0..100 {
+        let index = round % 2;
+        let before = STEPS[index].load(Ordering::Relaxed);
+        assert_eq!(coroutine_resume(cids[index]), 0);
+        assert!(STEPS[index].load(Ordering::Relaxed) > before);
+    }
+    STOP.store(true, Ordering::Relaxed);
+    while FINISHED.load(Ordering::Relaxed) < 2 {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+    println!(
+        "resumed coroutines switched out in user space, steps {} and {}",
+        STEPS[0].load(Ordering::Relaxed),
+        STEPS[1].load(Ordering::Relaxed)
+    );
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    run_round_robin();
+    run_resume();
+    // 子进程继承了调度页和登记过的切换代码
+    let pid = fork();
+    if pid == 0 {
+        run_round_robin();
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("coroutine_fast_yield passed!");
+    0
+}
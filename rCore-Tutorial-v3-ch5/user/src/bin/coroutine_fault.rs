@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineInfo, CoroutineStatus, FaultPolicy, coroutine_create, coroutine_create_with_stack,
+    coroutine_info, coroutine_resume, coroutine_set_fault_policy, coroutine_set_name,
+    coroutine_yield,
+};
+
+const ROUNDS: usize = 8;
+
+static SIBLING_ROUNDS: AtomicUsize = AtomicUsize::new(0);
+
+// 没有终止条件的递归，最终越过栈底的保护页
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let frame = [depth as u8; 64];
+    core::hint::black_box(&frame);
+    recurse(depth + 1) + frame[1] as usize
+}
+
+fn overflow(_: usize) -> i32 {
+    recurse(0);
+    0
+}
+
+fn null_write(_: usize) -> i32 {
+    unsafe {
+        core::ptr::null_mut::<u8>().write_volatile(0);
+    }
+    0
+}
+
+fn illegal(_: usize) -> i32 {
+    unsafe {
+        core::arch::asm!("unimp");
+    }
+    0
+}
+
+fn sibling(_: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        SIBLING_ROUNDS.fetch_add(1, Ordering::Relaxed);
+        coroutine_yield();
+    }
+    0
+}
+
+fn exit_code_of(cid: usize) -> isize {
+    let mut infos = [CoroutineInfo::default(); 8];
+    let total = coroutine_info(-1, &mut infos) as usize;
+    let info = infos[..total].iter().find(|info| info.cid == cid).unwrap();
+    assert_eq!(info.status(), CoroutineStatus::Exited);
+    info.exit_code
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(
+        coroutine_set_fault_policy(FaultPolicy::KillCoroutine),
+        FaultPolicy::KillProcess
+    );
+    let sibling = coroutine_create(sibling, 0);
+    let faulty = [
+        (coroutine_create_with_stack(overflow, 0, 4096 * 2), -2),
+        (coroutine_create(null_write, 0), -2),
+        (coroutine_create(illegal, 0), -3),
+    ];
+    for ((cid, _), name) in faulty.iter().zip(["overflow", "null-write", "illegal"]) {
+        coroutine_set_name(*cid, name);
+        coroutine_resume(*cid);
+    }
+    // the sibling and the main flow survive every fault
+    while SIBLING_ROUNDS.load(Ordering::Relaxed) < ROUNDS {
+        coroutine_yield();
+    }
+    coroutine_resume(sibling);
+    assert_eq!(exit_code_of(sibling), 0);
+    for (cid, expected) in faulty {
+        let exit_code = exit_code_of(cid);
+        println!("coroutine {} was killed with exit code {}", cid, exit_code);
+        assert_eq!(exit_code, expected);
+    }
+    coroutine_set_fault_policy(FaultPolicy::KillProcess);
+    println!("coroutine_fault passed!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineInfo, ForkMode, coroutine_create, coroutine_fork, coroutine_info, coroutine_resume,
+    coroutine_waitpid, coroutine_yield, exec, exit, fork, waitpid, yield_,
+};
+
+const ROUNDS: usize = 4;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static FORKER_DONE: AtomicBool = AtomicBool::new(false);
+
+fn counter(rounds: usize) -> i32 {
+    for _ in 0..rounds {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+        coroutine_yield();
+    }
+    0
+}
+
+fn count_coroutines() -> usize {
+    let mut infos = [CoroutineInfo::default(); 8];
+    coroutine_info(-1, &mut infos) as usize
+}
+
+fn forker(_: usize) -> i32 {
+    let pid = coroutine_fork(ForkMode::CurrentCoroutine);
+    if pid == 0 {
+        // only this coroutine survives, returning from it exits the child
+        assert_eq!(count_coroutines(), 1);
+        return 7;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(coroutine_waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    println!("child {} forked by a coroutine exited with code {}", pid, exit_code);
+    FORKER_DONE.store(true, Ordering::Relaxed);
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let mut exit_code: i32 = 0;
+
+    // fork copies the whole coroutine table, the counter carries on in the child
+    let cid = coroutine_create(counter, ROUNDS);
+    coroutine_resume(cid);
+    let total = count_coroutines();
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(count_coroutines(), total);
+        while coroutine_resume(cid) >= 0 {}
+        assert_eq!(COUNTER.load(Ordering::Relaxed), ROUNDS);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(COUNTER.load(Ordering::Relaxed), 1);
+
+    // a coroutine can fork a child that runs only itself
+    coroutine_create(forker, 0);
+    while !FORKER_DONE.load(Ordering::Relaxed) {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+
+    // exec discards every coroutine of the process
+    let pid = fork();
+    if pid == 0 {
+        coroutine_create(counter, ROUNDS);
+        exec("hello_world\0");
+        panic!("exec failed");
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("coroutine_fork passed!");
+    0
+}
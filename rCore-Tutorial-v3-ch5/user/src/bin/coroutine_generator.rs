@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{CoroutineInfo, Generator, coroutine_info, gen_yield};
+
+// 当前进程中还存在的协程数量，包括主执行流
+fn coroutine_count() -> isize {
+    let mut infos = [CoroutineInfo::default(); 16];
+    coroutine_info(-1, &mut infos)
+}
+
+fn fibonacci() -> Generator<usize> {
+    Generator::new(|| {
+        let (mut a, mut b) = (0, 1);
+        loop {
+            gen_yield(a);
+            (a, b) = (b, a + b);
+        }
+    })
+    .unwrap()
+}
+
+// 把输入切分成单词，代替手写的状态机
+fn words(text: &'static str) -> Generator<String> {
+    Generator::new(move || {
+        let mut word = String::new();
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !word.is_empty() {
+                    gen_yield(core::mem::take(&mut word));
+                }
+            } else {
+                word.push(c);
+            }
+        }
+        if !word.is_empty() {
+            gen_yield(word);
+        }
+    })
+    .unwrap()
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let base = coroutine_count();
+
+    // 无限的生成器只取前几项
+    let fib: Vec<usize> = fibonacci().take(10).collect();
+    assert_eq!(fib, [0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+    assert_eq!(coroutine_count(), base);
+
+    // 迭代完以后一直返回 None
+    let mut tokens = words("  let x =\n  42 ;");
+    let collected: Vec<String> = tokens.by_ref().collect();
+    assert_eq!(collected, ["let", "x", "=", "42", ";"]);
+    assert!(tokens.next().is_none());
+    assert_eq!(coroutine_count(), base);
+    drop(tokens);
+
+    // 嵌套：一个生成器的函数体迭代另一个生成器
+    let evens = Generator::new(|| {
+        for x in fibonacci().filter(|x| x % 2 == 0).take(5) {
+            gen_yield(x);
+        }
+    })
+    .unwrap();
+    assert_eq!(evens.collect::<Vec<usize>>(), [0, 2, 8, 34, 144]);
+
+    // 没有迭代完就丢弃，或者一次都没有迭代，协程都会退出并释放栈
+    let mut fib = fibonacci();
+    assert_eq!(fib.nth(3), Some(2));
+    let unused = words("never read");
+    assert_eq!(coroutine_count(), base + 2);
+    drop(fib);
+    drop(unused);
+    assert_eq!(coroutine_count(), base);
+
+    // 反复创建和丢弃不会耗尽栈
+    for i in 0..200 {
+        assert_eq!(fibonacci().nth(i % 20), fibonacci().nth(i % 20));
+    }
+    assert_eq!(coroutine_count(), base);
+    println!("coroutine_generator passed!");
+    0
+}
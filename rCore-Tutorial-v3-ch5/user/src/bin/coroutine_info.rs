@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    COROUTINE_NAME_LEN, CoroutineInfo, coroutine_create, coroutine_info, coroutine_resume, coroutine_set_name,
+    coroutine_yield, exit, fork, sleep, waitpid,
+};
+
+const MAX_COROUTINES: usize = 16;
+
+// 进程 pid（-1 表示当前进程）的协程总数，并把前 MAX_COROUTINES 个的信息填入 infos
+fn query(pid: isize, infos: &mut [CoroutineInfo; MAX_COROUTINES]) -> isize {
+    *infos = [CoroutineInfo::default(); MAX_COROUTINES];
+    coroutine_info(pid, infos)
+}
+
+fn worker(rounds: usize) -> i32 {
+    for _ in 0..rounds {
+        coroutine_yield();
+    }
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // coroutines of this process: one finished, one still ready
+    let done = coroutine_create(worker, 0);
+    let pending = coroutine_create(worker, 100);
+    coroutine_set_name(0, "main");
+    coroutine_set_name(done, "done");
+    coroutine_set_name(pending, "pending");
+    coroutine_resume(done);
+    coroutine_resume(pending);
+    let mut infos = [CoroutineInfo::default(); MAX_COROUTINES];
+    assert!(query(-1, &mut infos) >= 3);
+    assert_eq!(infos[0].cid, 0);
+    assert_eq!(infos[0].name(), "main");
+    let info = infos.iter().find(|info| info.cid == done).unwrap();
+    assert_eq!(info.name(), "done");
+    assert_eq!(info.status().as_str(), "Exited");
+
+    // names are truncated to COROUTINE_NAME_LEN - 1 bytes
+    assert_eq!(coroutine_set_name(pending, "a-very-long-coroutine-name"), 0);
+    assert_eq!(coroutine_set_name(10000, "nobody"), -1);
+    let total = query(-1, &mut infos) as usize;
+    let info = infos[..total].iter().find(|info| info.cid == pending).unwrap();
+    assert_eq!(info.name(), &"a-very-long-coroutine-name"[..COROUTINE_NAME_LEN - 1]);
+
+    // coroutines of a child process looked up by pid
+    let pid = fork();
+    if pid == 0 {
+        for rounds in 1..4 {
+            let cid = coroutine_create(worker, rounds * 100);
+            coroutine_set_name(cid, "child-worker");
+        }
+        coroutine_yield();
+        sleep(200);
+        exit(0);
+    }
+    sleep(100);
+    assert!(query(pid, &mut infos) >= 4);
+    assert_eq!(infos.iter().filter(|info| info.name() == "child-worker").count(), 3);
+    // any live process can be looked up, not only the children
+    assert!(query(0, &mut infos) >= 1);
+    assert_eq!(infos[0].cid, 0);
+    assert_eq!(query(10000, &mut infos), -1);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    println!("coroutine_info passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    coroutine_create_with_stack, coroutine_resume, coroutine_stack_usage, coroutine_yield,
+};
+
+const NUM_COROUTINES: usize = 1000;
+// 每个协程保留 64KiB 的栈，但内核只按实际使用量映射
+const STACK_LIMIT: usize = 64 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+fn small(arg: usize) -> i32 {
+    coroutine_yield();
+    (arg % 2) as i32
+}
+
+// 使用远超一页的栈，只能依靠缺页时扩展栈来完成
+#[inline(never)]
+fn deep(depth: usize) -> i32 {
+    let frame = [depth as u8; 256];
+    core::hint::black_box(&frame);
+    if depth == 0 {
+        return 0;
+    }
+    deep(depth - 1) + (frame[0] == 0) as i32
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // each coroutine is started right away so that its start-up box on the user
+    // heap is released before the next one is created
+    for i in 0..NUM_COROUTINES {
+        let cid = coroutine_create_with_stack(small, i, STACK_LIMIT);
+        coroutine_resume(cid);
+    }
+    while coroutine_yield() >= 0 {}
+    println!("{} coroutines with {} KiB stacks finished", NUM_COROUTINES, STACK_LIMIT / 1024);
+
+    let cid = coroutine_create_with_stack(deep, 64, STACK_LIMIT);
+    coroutine_resume(cid);
+    let usage = coroutine_stack_usage(cid);
+    println!("deep coroutine grew its stack to {} bytes", usage);
+    assert!(usage as usize > PAGE_SIZE * 2 && (usage as usize) <= STACK_LIMIT);
+    println!("coroutine_many passed!");
+    0
+}
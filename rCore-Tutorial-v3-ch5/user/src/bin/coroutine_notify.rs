@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    coroutine_create, coroutine_notify, coroutine_wait_notify, coroutine_waitpid, coroutine_yield,
+    exit, fork, get_time, getpid, sleep, yield_,
+};
+
+const NUM_CHILDREN: usize = 4;
+// 子进程唤醒父进程后占用CPU的时间
+const SPIN_MS: isize = 1000;
+const DONE: usize = usize::MAX;
+
+static SUM: AtomicUsize = AtomicUsize::new(0);
+
+// 把子进程发来的值累加起来，直到收到 DONE
+fn listener(_: usize) -> i32 {
+    loop {
+        match coroutine_wait_notify() {
+            DONE => return 0,
+            value => SUM.fetch_add(value, Ordering::Relaxed),
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // a notification to a coroutine that is not waiting is kept until it waits
+    let parent = getpid() as usize;
+    assert_eq!(coroutine_notify(parent, 0, 7), 1);
+    assert_eq!(coroutine_notify(parent, 0, 8), -2);
+    assert_eq!(coroutine_wait_notify(), 7);
+    assert_eq!(coroutine_notify(parent, 10000, 0), -1);
+
+    // children send values to the listener of their parent
+    let listener_cid = coroutine_create(listener, 0);
+    coroutine_yield();
+    let mut children = [0; NUM_CHILDREN];
+    for (i, child) in children.iter_mut().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            sleep(10 * i);
+            // the listener may not be back to waiting yet
+            while coroutine_notify(parent, listener_cid, i + 1) < 0 {
+                yield_();
+            }
+            exit(0);
+        }
+        *child = pid as usize;
+    }
+    let mut exit_code: i32 = 0;
+    for child in children {
+        assert_eq!(coroutine_waitpid(child, &mut exit_code), child as isize);
+    }
+    assert_eq!(SUM.load(Ordering::Relaxed), (1..=NUM_CHILDREN).sum());
+    coroutine_notify(parent, listener_cid, DONE);
+    coroutine_yield();
+
+    // the parent wakes a coroutine of its child
+    let child = fork();
+    if child == 0 {
+        let value = coroutine_wait_notify();
+        exit(value as i32);
+    }
+    // the main flow of the child is coroutine 0
+    while coroutine_notify(child as usize, 0, 42) < 0 {
+        yield_();
+    }
+    assert_eq!(coroutine_waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 42);
+
+    // a child that keeps computing after waking its parked parent is still
+    // preempted, so the parent runs long before the child is done
+    let start = get_time();
+    let child = fork();
+    if child == 0 {
+        sleep(10);
+        assert!(coroutine_notify(parent, 0, 1) >= 0);
+        while get_time() < start + SPIN_MS {}
+        exit(0);
+    }
+    assert_eq!(coroutine_wait_notify(), 1);
+    assert!(get_time() < start + SPIN_MS);
+    assert_eq!(coroutine_waitpid(child as usize, &mut exit_code), child);
+    // processes other than the parent and the children cannot be notified
+    assert_eq!(coroutine_notify(10000, 0, 0), -1);
+    println!("coroutine_notify passed!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineId, CoroutineInfo, coroutine_create, coroutine_create_with_stack, coroutine_detach,
+    coroutine_info, coroutine_resume, coroutine_set_stack_cache_limit, coroutine_trim_stack_cache,
+    coroutine_yield, get_time,
+};
+
+const REQUESTS: usize = 500;
+const STACK_SIZE: usize = 8192;
+// 内核保留的未分离的已退出协程数量
+const EXITED_RETAIN_LIMIT: usize = 32;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+// 每个请求由一个短命的协程处理
+fn handle(_: usize) -> i32 {
+    coroutine_yield();
+    HANDLED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn stack_base_of(cid: CoroutineId) -> usize {
+    let mut infos = [CoroutineInfo::default(); 4];
+    let total = coroutine_info(-1, &mut infos) as usize;
+    infos[..total].iter().find(|info| info.cid == cid).unwrap().stack_base
+}
+
+// 创建一个分离的请求协程并运行到结束，返回它的栈基址
+fn serve(stack_size: usize) -> usize {
+    let cid = coroutine_create_with_stack(handle, 0, stack_size);
+    coroutine_detach(cid);
+    let stack_base = stack_base_of(cid);
+    while coroutine_resume(cid) >= 0 {}
+    stack_base
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // every request after the first one runs on the same cached stack
+    let start = get_time();
+    let first = serve(STACK_SIZE);
+    for _ in 1..REQUESTS {
+        assert_eq!(serve(STACK_SIZE), first);
+    }
+    assert_eq!(HANDLED.load(Ordering::Relaxed), REQUESTS);
+    println!("{} requests served in {} ms", REQUESTS, get_time() - start);
+
+    // a cached stack is only reused when it is large enough
+    let big = serve(STACK_SIZE * 4);
+    assert_ne!(big, first);
+    // the smallest fitting stack is picked
+    assert_eq!(serve(STACK_SIZE), first);
+    assert_eq!(serve(STACK_SIZE * 2), big);
+
+    // trimming frees the cached stacks, new coroutines get fresh ones
+    assert_eq!(coroutine_trim_stack_cache(0), 2);
+    let fresh = serve(STACK_SIZE);
+    assert!(fresh != first && fresh != big);
+
+    // without a cache every exited stack is freed right away
+    assert_eq!(coroutine_set_stack_cache_limit(0), 8);
+    assert_eq!(coroutine_trim_stack_cache(0), 0);
+    assert_ne!(serve(STACK_SIZE), fresh);
+    let cid = coroutine_create(handle, 0);
+    while coroutine_resume(cid) >= 0 {}
+    assert_eq!(coroutine_trim_stack_cache(0), 0);
+
+    // only the most recently exited of the coroutines that are not detached are kept
+    for _ in 0..REQUESTS {
+        let cid = coroutine_create(handle, 0);
+        while coroutine_resume(cid) >= 0 {}
+    }
+    let mut infos = [CoroutineInfo::default(); 64];
+    let total = coroutine_info(-1, &mut infos) as usize;
+    assert!(total <= EXITED_RETAIN_LIMIT + 1);
+    println!("coroutine_pool passed!");
+    0
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::mem::MaybeUninit;
+use user_lib::{
+    coroutine_create_with_stack, coroutine_resume, coroutine_stack_usage, exit, fork, waitpid,
+    write,
+};
+
+const STACK_SIZE: usize = 4096 * 4;
+const CHILD_EXIT_CODE: i32 = 7;
+
+// 每层递归占用一块栈帧，递归深度由参数决定
+#[inline(never)]
+fn recurse(depth: usize) -> usize {
+    let frame = [depth as u8; 64];
+    if depth == 0 {
+        return frame[0] as usize;
+    }
+    core::hint::black_box(&frame);
+    recurse(depth - 1) + frame[1] as usize
+}
+
+fn worker(depth: usize) -> i32 {
+    recurse(depth);
+    0
+}
+
+// 让内核把退出码写到远离栈顶、用户态还没有访问过的栈页中
+#[inline(never)]
+fn wait_deep(pid: usize) -> i32 {
+    let mut frame = MaybeUninit::<[i32; 4096]>::uninit();
+    let exit_code = unsafe { &mut (*frame.as_mut_ptr())[0] };
+    assert_eq!(waitpid(pid, exit_code), pid as isize);
+    *exit_code
+}
+
+fn waiter(pid: usize) -> i32 {
+    assert_eq!(wait_deep(pid), CHILD_EXIT_CODE);
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let mut last_usage = 0;
+    for depth in [0, 16, 64] {
+        let cid = coroutine_create_with_stack(worker, depth, STACK_SIZE);
+        coroutine_resume(cid);
+        let usage = coroutine_stack_usage(cid);
+        println!(
+            "coroutine {} recursed {} levels, stack high watermark {} of {} bytes",
+            cid, depth, usage, STACK_SIZE
+        );
+        assert!(usage > last_usage && (usage as usize) < STACK_SIZE);
+        last_usage = usage;
+    }
+    // the main flow has no coroutine stack of its own
+    assert_eq!(coroutine_stack_usage(0), -1);
+    // stack sizes that are empty, not whole pages or too large are rejected
+    for size in [0, 100, STACK_SIZE + 1, 2 * 1024 * 1024, usize::MAX] {
+        assert_eq!(coroutine_create_with_stack(worker, 0, size) as isize, -1);
+    }
+    // a system call may write to stack pages the coroutine has not touched yet
+    let pid = fork();
+    if pid == 0 {
+        exit(CHILD_EXIT_CODE);
+    }
+    let cid = coroutine_create_with_stack(waiter, pid as usize, STACK_SIZE * 4);
+    coroutine_resume(cid);
+    assert!(coroutine_stack_usage(cid) as usize > STACK_SIZE);
+    // and fails on memory that is not mapped at all
+    let unmapped = unsafe { core::slice::from_raw_parts(0x4000_0000 as *const u8, 16) };
+    assert_eq!(write(1, unmapped), -1);
+    println!("coroutine_stack passed!");
+    0
+}
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineStats, coroutine_create, coroutine_resume, coroutine_set_name, coroutine_stats,
+    coroutine_waitpid, coroutine_yield, exit, fork, get_time, sleep, yield_,
+};
+
+const SPIN_MS: usize = 100;
+const ROUNDS: usize = 20;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+// 长时间占用CPU而不让出
+fn hog(_: usize) -> i32 {
+    let start = get_time();
+    while ((get_time() - start) as usize) < SPIN_MS {}
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+// 频繁让出CPU
+fn polite(_: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        coroutine_yield();
+    }
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+// 大部分时间在等待子进程
+fn sleeper(_: usize) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        sleep(SPIN_MS);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    coroutine_waitpid(pid as usize, &mut exit_code);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn report(name: &str, stats: &CoroutineStats) {
+    println!(
+        "{:<8} scheduled {:>3}, voluntary {:>3}, involuntary {:>3}, run {:>7} us, blocked {:>7} us",
+        name,
+        stats.schedule_count,
+        stats.voluntary_switches,
+        stats.involuntary_switches,
+        stats.run_time_us,
+        stats.blocked_time_us,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let names = ["hog", "polite", "sleeper"];
+    let cids = [
+        coroutine_create(hog, 0),
+        coroutine_create(polite, 0),
+        coroutine_create(sleeper, 0),
+    ];
+    for (cid, name) in cids.iter().zip(names) {
+        coroutine_set_name(*cid, name);
+    }
+    for cid in cids {
+        coroutine_resume(cid);
+    }
+    while FINISHED.load(Ordering::Relaxed) < cids.len() {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+
+    let stats = cids.map(|cid| coroutine_stats(cid).unwrap());
+    for (name, stats) in names.iter().zip(stats.iter()) {
+        report(name, stats);
+    }
+    let [hog, polite, sleeper] = stats;
+    // the hog used most of the CPU and was preempted while spinning
+    assert!(hog.run_time_us >= SPIN_MS * 1000 / 2);
+    assert!(hog.run_time_us > polite.run_time_us && hog.run_time_us > sleeper.run_time_us);
+    assert!(hog.involuntary_switches > 0);
+    assert_eq!(hog.voluntary_switches, 1);
+    // the polite coroutine switched out on every round and when it exited
+    assert_eq!(polite.voluntary_switches, ROUNDS + 1);
+    // the sleeper spent its time blocked
+    assert!(sleeper.blocked_time_us > 0);
+    assert!(coroutine_stats(10000).is_none());
+    println!("coroutine_stats passed!");
+    0
+}
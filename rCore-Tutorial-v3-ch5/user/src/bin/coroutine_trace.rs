@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::println_empty_string)]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+
+const BATCH: usize = 64;
+
+use alloc::string::String;
+use user_lib::console::getchar;
+use user_lib::{TraceRecord, coroutine_trace, exec, fork, trace_read, waitpid};
+
+// 读入一个应用名，在开启协程跟踪的子进程中运行它，结束后输出内核记录的全部跟踪事件。
+// 输出可以用 user/scripts/trace2json.py 转换后在 chrome://tracing 或 Perfetto 中查看
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    print!("trace app: ");
+    let mut line: String = String::new();
+    loop {
+        let c = getchar();
+        match c {
+            LF | CR => {
+                println!("");
+                if !line.is_empty() {
+                    break;
+                }
+            }
+            BS | DL => {
+                if !line.is_empty() {
+                    print!("{}", BS as char);
+                    print!(" ");
+                    print!("{}", BS as char);
+                    line.pop();
+                }
+            }
+            _ => {
+                print!("{}", c as char);
+                line.push(c as char);
+            }
+        }
+    }
+    // 丢掉之前其他进程留下的记录
+    let mut records = [TraceRecord::default(); BATCH];
+    while trace_read(&mut records) > 0 {}
+
+    line.push('\0');
+    let pid = fork();
+    if pid == 0 {
+        // 跟踪在exec之后保持开启
+        coroutine_trace(true);
+        if exec(line.as_str()) == -1 {
+            println!("Error when executing!");
+            return -4;
+        }
+        unreachable!();
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    loop {
+        let count = trace_read(&mut records);
+        if count == 0 {
+            break;
+        }
+        for record in &records[..count] {
+            println!("{}", record);
+        }
+    }
+    println!("coroutine_trace: process {} exited with code {}", pid, exit_code);
+    0
+}
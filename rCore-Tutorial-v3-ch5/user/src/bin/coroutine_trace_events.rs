@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    TraceKind, TraceRecord, coroutine_create, coroutine_resume, coroutine_suspend,
+    coroutine_trace, getpid, trace_read,
+};
+
+// 主协程的ID
+const MAIN_CID: usize = 0;
+
+fn worker(_: usize) -> i32 {
+    coroutine_suspend();
+    3
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // 丢掉之前其他进程留下的记录
+    let mut records = [TraceRecord::default(); 64];
+    while trace_read(&mut records) > 0 {}
+
+    assert!(!coroutine_trace(true));
+    let cid = coroutine_create(worker, 0);
+    coroutine_resume(cid);
+    coroutine_resume(cid);
+    assert!(coroutine_trace(false));
+
+    let expected = [
+        (cid, TraceKind::Create, Some(MAIN_CID)),
+        (MAIN_CID, TraceKind::SwitchOut, None),
+        (cid, TraceKind::SwitchIn, None),
+        (cid, TraceKind::Block, None),
+        (cid, TraceKind::SwitchOut, None),
+        (MAIN_CID, TraceKind::SwitchIn, None),
+        (cid, TraceKind::Unblock, None),
+        (MAIN_CID, TraceKind::SwitchOut, None),
+        (cid, TraceKind::SwitchIn, None),
+        (cid, TraceKind::Exit, Some(3)),
+        (cid, TraceKind::SwitchOut, None),
+        (MAIN_CID, TraceKind::SwitchIn, None),
+    ];
+    let pid = getpid() as usize;
+    let count = trace_read(&mut records);
+    let mut matched = 0;
+    let mut last_time = 0;
+    for record in records[..count].iter().filter(|r| r.pid == pid) {
+        println!("{}", record);
+        assert!(matched < expected.len(), "unexpected record {}", record);
+        let (cid, kind, arg) = expected[matched];
+        assert_eq!(record.cid, cid);
+        assert_eq!(record.kind(), kind);
+        if let Some(arg) = arg {
+            assert_eq!(record.arg, arg);
+        }
+        assert!(record.time_us >= last_time);
+        last_time = record.time_us;
+        matched += 1;
+    }
+    assert_eq!(matched, expected.len());
+    println!("coroutine_trace_events passed!");
+    0
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    CoroutineInfo, CoroutineStatus, UpcallEvent, coroutine_clear_upcall, coroutine_create,
+    coroutine_info, coroutine_notify, coroutine_resume, coroutine_set_upcall,
+    coroutine_wait_notify, coroutine_yield, getpid, yield_,
+};
+
+const MAX_EVENTS: usize = 8;
+
+// 处理函数收到的事件，每项为 (事件 << 16) | 协程ID
+static EVENTS: [AtomicUsize; MAX_EVENTS] = [const { AtomicUsize::new(0) }; MAX_EVENTS];
+static EVENT_LEN: AtomicUsize = AtomicUsize::new(0);
+static WAITER: AtomicUsize = AtomicUsize::new(0);
+static NOTIFIER: AtomicUsize = AtomicUsize::new(0);
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn record(event: UpcallEvent, cid: usize) -> usize {
+    ((event as usize) << 16) | cid
+}
+
+// 一个极简的用户态运行时：等待者一阻塞就立即运行负责通知它的协程
+fn on_upcall(event: UpcallEvent, cid: usize) {
+    let index = EVENT_LEN.fetch_add(1, Ordering::Relaxed);
+    EVENTS[index].store(record(event, cid), Ordering::Relaxed);
+    if event == UpcallEvent::Blocked && cid == WAITER.load(Ordering::Relaxed) {
+        coroutine_resume(NOTIFIER.load(Ordering::Relaxed));
+    }
+}
+
+fn waiter(expected: usize) -> i32 {
+    assert_eq!(coroutine_wait_notify(), expected);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn notifier(value: usize) -> i32 {
+    let waiter = WAITER.load(Ordering::Relaxed);
+    assert_eq!(coroutine_notify(getpid() as usize, waiter, value), 0);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn wait_finished(count: usize) {
+    while FINISHED.load(Ordering::Relaxed) < count {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    WAITER.store(coroutine_create(waiter, 7), Ordering::Relaxed);
+    NOTIFIER.store(coroutine_create(notifier, 7), Ordering::Relaxed);
+    let upcall_cid = coroutine_set_upcall(on_upcall);
+    assert!(upcall_cid > 0);
+    wait_finished(2);
+
+    let waiter_cid = WAITER.load(Ordering::Relaxed);
+    assert_eq!(EVENT_LEN.load(Ordering::Relaxed), 2);
+    assert_eq!(
+        EVENTS[0].load(Ordering::Relaxed),
+        record(UpcallEvent::Blocked, waiter_cid)
+    );
+    assert_eq!(
+        EVENTS[1].load(Ordering::Relaxed),
+        record(UpcallEvent::Unblocked, waiter_cid)
+    );
+    println!("upcalls: coroutine {} blocked, then unblocked", waiter_cid);
+
+    // 处理函数在自己的协程上运行，没有事件时处于阻塞状态
+    let mut infos = [CoroutineInfo::default(); 8];
+    let count = coroutine_info(-1, &mut infos) as usize;
+    let upcall = infos[..count]
+        .iter()
+        .find(|info| info.cid == upcall_cid as usize)
+        .unwrap();
+    assert_eq!(upcall.name(), "upcall");
+    assert_eq!(upcall.status(), CoroutineStatus::Blocked);
+
+    // 暂停后阻塞和唤醒不再通知
+    assert_eq!(coroutine_clear_upcall(), upcall_cid);
+    WAITER.store(coroutine_create(waiter, 9), Ordering::Relaxed);
+    coroutine_yield();
+    coroutine_notify(getpid() as usize, WAITER.load(Ordering::Relaxed), 9);
+    wait_finished(3);
+    assert_eq!(EVENT_LEN.load(Ordering::Relaxed), 2);
+    println!("coroutine_upcall passed!");
+    0
+}
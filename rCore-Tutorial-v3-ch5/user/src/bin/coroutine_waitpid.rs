@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    coroutine_create, coroutine_waitpid, coroutine_yield, exit, fork, getpid, sleep, yield_,
+};
+
+const NUM_CHILDREN: usize = 4;
+
+static REAPED: AtomicUsize = AtomicUsize::new(0);
+
+// 每个子进程对应一个监视协程，只有这个协程会在等待时被挂起
+fn monitor(pid: usize) -> i32 {
+    let mut exit_code: i32 = 0;
+    let exit_pid = coroutine_waitpid(pid, &mut exit_code);
+    assert_eq!(exit_pid, pid as isize);
+    println!("monitor: child {} exited with code {}", pid, exit_code);
+    REAPED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    for i in 0..NUM_CHILDREN {
+        let pid = fork();
+        if pid == 0 {
+            sleep(50 * (NUM_CHILDREN - i));
+            println!("child {} done", getpid());
+            exit(i as i32);
+        }
+        coroutine_create(monitor, pid as usize);
+    }
+    // the main flow keeps working while every monitor is parked
+    let mut rounds = 0;
+    while REAPED.load(Ordering::Relaxed) < NUM_CHILDREN {
+        if coroutine_yield() < 0 {
+            rounds += 1;
+            yield_();
+        }
+    }
+    println!("main flow ran {} rounds while monitors waited", rounds);
+    println!("coroutine_waitpid passed!");
+    0
+}
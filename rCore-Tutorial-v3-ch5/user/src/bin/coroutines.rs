@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::println_empty_string)]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+
+use alloc::string::String;
+use user_lib::console::getchar;
+use user_lib::{CoroutineInfo, coroutine_info};
+
+const MAX_COROUTINES: usize = 64;
+
+// 从标准输入读入一行
+fn read_line() -> String {
+    let mut line = String::new();
+    loop {
+        match getchar() {
+            LF | CR => {
+                println!("");
+                return line;
+            }
+            BS | DL => {
+                if line.pop().is_some() {
+                    print!("{}", BS as char);
+                    print!(" ");
+                    print!("{}", BS as char);
+                }
+            }
+            c => {
+                print!("{}", c as char);
+                line.push(c as char);
+            }
+        }
+    }
+}
+
+// 打印进程 pid（-1 表示当前进程）的协程表
+fn print_coroutines(pid: isize) -> isize {
+    let mut infos = [CoroutineInfo::default(); MAX_COROUTINES];
+    let total = coroutine_info(pid, &mut infos);
+    if total < 0 {
+        println!("coroutines: no such process {}", pid);
+        return total;
+    }
+    println!("coroutines of process {}: {} in total", pid, total);
+    println!("  cid name            status   entry      stack              switches run(us)");
+    for info in infos.iter().take(total as usize) {
+        println!(
+            "  {:>3} {:<15} {:<8} {:#010x} {:#x}+{:<#7x} {:>8} {}",
+            info.cid,
+            info.name(),
+            info.status().as_str(),
+            info.entry,
+            info.stack_base,
+            info.stack_size,
+            info.switch_count,
+            info.run_time_us,
+        );
+    }
+    if total as usize > MAX_COROUTINES {
+        println!("  ... {} more", total as usize - MAX_COROUTINES);
+    }
+    total
+}
+
+// 打印任意存活进程的协程表，pid 从标准输入读入，直接回车表示本进程
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    print!("pid (empty for this process): ");
+    let line = read_line();
+    let line = line.trim();
+    let pid = if line.is_empty() {
+        -1
+    } else {
+        match line.parse::<isize>() {
+            Ok(pid) if pid >= 0 => pid,
+            _ => {
+                println!("coroutines: invalid pid {}", line);
+                return -1;
+            }
+        }
+    };
+    if print_coroutines(pid) < 0 { -1 } else { 0 }
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, getpid, times, waitpid, yield_};
+
+const SPIN_MS: isize = 100;
+
+fn spin(ms: isize) {
+    let end = get_time() + ms;
+    while get_time() < end {}
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // 空转的时间大部分记在用户态
+    let before = times();
+    spin(SPIN_MS);
+    let after = times();
+    let user_us = after.user_us - before.user_us;
+    println!("spin {} ms: user {} us", SPIN_MS, user_us);
+    assert!(user_us >= SPIN_MS as usize * 1000 / 2);
+
+    // 系统调用的时间记在内核
+    let before = times();
+    for _ in 0..1000 {
+        getpid();
+    }
+    let after = times();
+    println!("1000 getpid: sys {} us", after.kernel_us - before.kernel_us);
+    assert!(after.kernel_us > before.kernel_us);
+
+    // 每次 yield 都是一次主动切换
+    let before = times();
+    for _ in 0..10 {
+        yield_();
+    }
+    let after = times();
+    assert!(after.voluntary_switches >= before.voluntary_switches + 10);
+
+    // 子进程的时间在回收后计入
+    let before = times();
+    let pid = fork();
+    if pid == 0 {
+        spin(SPIN_MS);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let after = times();
+    let children_user_us = after.children_user_us - before.children_user_us;
+    println!("child spin {} ms: user {} us", SPIN_MS, children_user_us);
+    assert!(children_user_us >= SPIN_MS as usize * 1000 / 2);
+    // 子进程的时间不计入父进程自己
+    assert!(after.user_us - before.user_us < children_user_us);
+
+    let now = times();
+    println!(
+        "user {} us, sys {} us, {} voluntary / {} involuntary switches",
+        now.user_us, now.kernel_us, now.voluntary_switches, now.involuntary_switches
+    );
+    println!("cpu_times passed!");
+    0
+}
@@ -0,0 +1,242 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::needless_range_loop)]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{coroutine_create, coroutine_yield, exit, fork, getpid, wait, yield_};
+
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_COROUTINE_YIELD: usize = 601;
+const PROCESSES: usize = 4;
+const COROUTINES: usize = 3;
+const ROUNDS: usize = 50;
+const N: usize = 8;
+type Arr = [[f64; N]; N];
+
+// 32个浮点寄存器的值和 fcsr
+type FpState = [u64; 33];
+
+// fp_check_trap 在装入全部浮点寄存器和 fcsr 后反复执行系统调用，再把它们读出来；
+// fp_check_call 只装入被调用者保存的 fs0-fs11 和 fcsr，中间改为调用一个函数
+global_asm!(
+    r#"
+    .option push
+    .option arch, +d
+    .section .text
+    .globl fp_check_trap
+    .globl fp_check_call
+    .align 2
+# a0: 装入的值, a1: 读出的值, a2: 次数, a3: 系统调用号
+fp_check_trap:
+    addi sp, sp, -96
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11
+    fsd fs\n, \n*8(sp)
+    .endr
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+    fld f\n, \n*8(a0)
+    .endr
+    ld t0, 32*8(a0)
+    fscsr t0
+    mv t1, a1
+    mv a7, a3
+1:
+    li a0, 0
+    ecall
+    addi a2, a2, -1
+    bnez a2, 1b
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+    fsd f\n, \n*8(t1)
+    .endr
+    frcsr t0
+    sd t0, 32*8(t1)
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11
+    fld fs\n, \n*8(sp)
+    .endr
+    addi sp, sp, 96
+    ret
+
+# a0: 装入的值, a1: 读出的值, a2: 次数, a3: 调用的函数
+fp_check_call:
+    addi sp, sp, -128
+    sd ra, 96(sp)
+    sd s0, 104(sp)
+    sd s1, 112(sp)
+    sd s2, 120(sp)
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11
+    fsd fs\n, \n*8(sp)
+    .endr
+    fld fs0, 8*8(a0)
+    fld fs1, 9*8(a0)
+    .irp n, 2,3,4,5,6,7,8,9,10,11
+    fld fs\n, (\n+16)*8(a0)
+    .endr
+    ld t0, 32*8(a0)
+    fscsr t0
+    mv s0, a1
+    mv s1, a2
+    mv s2, a3
+1:
+    jalr s2
+    addi s1, s1, -1
+    bnez s1, 1b
+    fsd fs0, 8*8(s0)
+    fsd fs1, 9*8(s0)
+    .irp n, 2,3,4,5,6,7,8,9,10,11
+    fsd fs\n, (\n+16)*8(s0)
+    .endr
+    frcsr t0
+    sd t0, 32*8(s0)
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11
+    fld fs\n, \n*8(sp)
+    .endr
+    ld ra, 96(sp)
+    ld s0, 104(sp)
+    ld s1, 112(sp)
+    ld s2, 120(sp)
+    addi sp, sp, 128
+    ret
+    .option pop
+"#
+);
+
+unsafe extern "C" {
+    fn fp_check_trap(values: *const FpState, out: *mut FpState, rounds: usize, syscall_id: usize);
+    fn fp_check_call(
+        values: *const FpState,
+        out: *mut FpState,
+        rounds: usize,
+        func: extern "C" fn(),
+    );
+}
+
+// 每个执行流装入互不相同的值，fcsr 中是舍入模式和异常标志
+fn fp_state(seed: usize) -> FpState {
+    let mut state = [0; 33];
+    for i in 0..32 {
+        state[i] = ((seed * 100 + i) as f64 + 0.25).to_bits();
+    }
+    state[32] = (((seed % 5) << 5) | (seed & 0x1f)) as u64;
+    state
+}
+
+// fs0-fs11 在寄存器编号中的位置
+fn callee_saved(index: usize) -> bool {
+    matches!(index, 8 | 9 | 18..=27)
+}
+
+fn check_trap(seed: usize, syscall_id: usize) {
+    let values = fp_state(seed);
+    let mut out = [0; 33];
+    unsafe { fp_check_trap(&values, &mut out, ROUNDS, syscall_id) };
+    assert_eq!(values, out, "FP state of {} corrupted", seed);
+}
+
+extern "C" fn do_coroutine_yield() {
+    coroutine_yield();
+}
+
+fn check_call(seed: usize) {
+    let values = fp_state(seed);
+    let mut out = [0; 33];
+    unsafe { fp_check_call(&values, &mut out, ROUNDS, do_coroutine_yield) };
+    for i in (0..32).filter(|&i| callee_saved(i)) {
+        assert_eq!(
+            values[i], out[i],
+            "fs register f{} of {} corrupted",
+            i, seed
+        );
+    }
+    assert_eq!(values[32], out[32], "fcsr of {} corrupted", seed);
+}
+
+fn float_matrix(times: usize) -> f64 {
+    let mut a: Arr = [[0.0; N]; N];
+    let mut c: Arr = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            a[i][j] = 1.0 / ((i + j + 1) as f64);
+        }
+    }
+    for _ in 0..times {
+        for i in 0..N {
+            for j in 0..N {
+                c[i][j] = 0.0;
+                for k in 0..N {
+                    c[i][j] += a[i][k] * a[k][j];
+                }
+            }
+        }
+        // 归一化，避免溢出
+        let norm = c[0][0];
+        for i in 0..N {
+            for j in 0..N {
+                a[i][j] = c[i][j] / norm;
+            }
+        }
+        yield_();
+    }
+    a.iter().flatten().sum()
+}
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn trap_worker(seed: usize) -> i32 {
+    check_trap(seed, SYSCALL_COROUTINE_YIELD);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn call_worker(seed: usize) -> i32 {
+    check_call(seed);
+    FINISHED.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+fn wait_coroutines(count: usize) {
+    while FINISHED.load(Ordering::Relaxed) < count {
+        if coroutine_yield() < 0 {
+            yield_();
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let expected = float_matrix(ROUNDS);
+    // 进程之间切换，包括时钟中断时的抢占
+    for _ in 0..PROCESSES {
+        let pid = fork();
+        if pid == 0 {
+            let seed = getpid() as usize;
+            check_trap(seed, SYSCALL_YIELD);
+            assert_eq!(float_matrix(ROUNDS).to_bits(), expected.to_bits());
+            check_trap(seed, SYSCALL_YIELD);
+            exit(0);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..PROCESSES {
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    println!("FP state kept across task switches");
+
+    // 协程经内核切换
+    for seed in 1..=COROUTINES {
+        coroutine_create(trap_worker, 1000 + seed);
+    }
+    wait_coroutines(COROUTINES);
+    // 协程在用户态切换
+    FINISHED.store(0, Ordering::Relaxed);
+    for seed in 1..=COROUTINES {
+        coroutine_create(call_worker, 2000 + seed);
+    }
+    wait_coroutines(COROUTINES);
+    println!("FP state kept across coroutine switches");
+    println!("fp_context passed!");
+    0
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{SchedPolicy, exec, exit, fork, get_time, sched_setpolicy, waitpid};
+
+const POLICIES: [SchedPolicy; 5] = [
+    SchedPolicy::Fifo,
+    SchedPolicy::RoundRobin,
+    SchedPolicy::Mlfq,
+    SchedPolicy::Cfs,
+    SchedPolicy::Stride,
+];
+// 在每种策略下运行的负载
+const WORKLOADS: [&str; 2] = ["forktree\0", "matrix\0"];
+
+// 运行一个应用直到它退出，返回耗时（毫秒）
+fn run(app: &str) -> isize {
+    let start = get_time();
+    let pid = fork();
+    if pid == 0 {
+        exec(app);
+        println!("sched_compare: {} not found", app);
+        exit(-1);
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    if exit_code != 0 {
+        println!("sched_compare: {} exited with {}", app, exit_code);
+    }
+    get_time() - start
+}
+
+// 依次在每种调度策略下运行同样的负载并计时，最后恢复原来的策略
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let mut elapsed = [[0; WORKLOADS.len()]; POLICIES.len()];
+    let mut original = None;
+    for (policy, elapsed) in POLICIES.iter().zip(elapsed.iter_mut()) {
+        let previous = match sched_setpolicy(*policy, 0) {
+            Some(previous) => previous,
+            None => {
+                println!("sched_compare: can not switch policies with SCHED_SEED");
+                return -1;
+            }
+        };
+        original.get_or_insert(previous);
+        for (app, elapsed) in WORKLOADS.iter().zip(elapsed.iter_mut()) {
+            *elapsed = run(app);
+        }
+    }
+    sched_setpolicy(original.unwrap(), 0);
+
+    println!("policy      forktree(ms)  matrix(ms)");
+    for (policy, elapsed) in POLICIES.iter().zip(elapsed.iter()) {
+        println!(
+            "{:<12}{:<14}{}",
+            policy_name(*policy),
+            elapsed[0],
+            elapsed[1]
+        );
+    }
+    0
+}
+
+fn policy_name(policy: SchedPolicy) -> &'static str {
+    match policy {
+        SchedPolicy::Fifo => "fifo",
+        SchedPolicy::RoundRobin => "rr",
+        SchedPolicy::Mlfq => "mlfq",
+        SchedPolicy::Cfs => "cfs",
+        SchedPolicy::Stride => "stride",
+    }
+}
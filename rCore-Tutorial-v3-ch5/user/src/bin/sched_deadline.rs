@@ -0,0 +1,126 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, get_time_us, sched_set_deadline, waitpid, yield_};
+
+// 与实时进程竞争CPU的普通进程个数
+const SPINNERS: usize = 4;
+const PERIOD_US: usize = 30_000;
+const JOBS: usize = 20;
+// 周期到达后最迟在下一个时钟中断被调度，再加上一些余量
+const MAX_GAP_US: isize = PERIOD_US as isize + 15_000;
+
+// 空转 ms 毫秒，返回执行的轮数（千轮）
+fn spin(ms: isize) -> i32 {
+    let end = get_time() + ms;
+    let mut rounds = 0;
+    while get_time() < end {
+        rounds += 1;
+    }
+    rounds / 1000
+}
+
+fn fork_spinner(ms: isize) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        exit(spin(ms));
+    }
+    pid as usize
+}
+
+fn wait_for(pid: usize) -> i32 {
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+    exit_code
+}
+
+// 参数检查和总利用率的接纳控制
+fn admission() {
+    // runtime > deadline
+    assert_eq!(sched_set_deadline(20_000, 50_000, 10_000), -1);
+    // deadline > period
+    assert_eq!(sched_set_deadline(10_000, 50_000, 60_000), -1);
+    // 周期最长60秒
+    assert_eq!(sched_set_deadline(10_000, 60_000_001, 60_000_001), -1);
+    assert_eq!(sched_set_deadline(usize::MAX, usize::MAX, usize::MAX), -1);
+    // runtime 乘以利用率的单位会溢出
+    assert_eq!(
+        sched_set_deadline(usize::MAX / 1000, usize::MAX, usize::MAX),
+        -1
+    );
+    // 边界上的参数：独占CPU超出上限，上限以内可以接纳
+    assert_eq!(sched_set_deadline(60_000_000, 60_000_000, 60_000_000), -2);
+    assert_eq!(sched_set_deadline(54_000_060, 60_000_000, 60_000_000), -2);
+    assert_eq!(sched_set_deadline(54_000_000, 60_000_000, 60_000_000), 0);
+    assert_eq!(sched_set_deadline(0, 0, 0), 0);
+    assert_eq!(sched_set_deadline(500_000, 1_000_000, 1_000_000), 0);
+    // 子进程是普通进程，再申请一半的CPU会超出上限
+    let pid = fork();
+    if pid == 0 {
+        exit(sched_set_deadline(500_000, 1_000_000, 1_000_000) as i32);
+    }
+    assert_eq!(wait_for(pid as usize), -2);
+    // 替换自己的参数时只计算新的利用率
+    assert_eq!(sched_set_deadline(800_000, 1_000_000, 1_000_000), 0);
+    assert_eq!(sched_set_deadline(950_000, 1_000_000, 1_000_000), -2);
+    assert_eq!(sched_set_deadline(0, 0, 0), 0);
+    println!("admission passed");
+}
+
+// 有普通进程占满CPU时，实时进程仍然每个周期都能运行
+fn periodic() {
+    let spinners: [usize; SPINNERS] = core::array::from_fn(|_| fork_spinner(1000));
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(sched_set_deadline(2_000, PERIOD_US, PERIOD_US), 0);
+        let mut last = get_time_us();
+        let mut max_gap = 0;
+        for _ in 0..JOBS {
+            // 本周期的工作已经完成
+            yield_();
+            let now = get_time_us();
+            max_gap = max_gap.max(now - last);
+            last = now;
+        }
+        println!("periodic: max gap between jobs {} us", max_gap);
+        exit((max_gap < MAX_GAP_US) as i32);
+    }
+    assert_eq!(wait_for(pid as usize), 1);
+    for pid in spinners {
+        wait_for(pid);
+    }
+    println!("periodic passed");
+}
+
+// 一直不让出的实时进程用完每个周期的运行时间后被限流，普通进程仍能得到大部分CPU时间
+fn budget() {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(sched_set_deadline(10_000, 50_000, 50_000), 0);
+        exit(spin(500));
+    }
+    let normal = spin(500);
+    let rt = wait_for(pid as usize);
+    println!(
+        "budget: real-time {} k rounds, normal {} k rounds",
+        rt, normal
+    );
+    assert!(normal > rt * 2);
+    println!("budget passed");
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    if sched_set_deadline(0, 0, 0) == -1 {
+        println!("sched_deadline: deterministic scheduling, skipped");
+        return 0;
+    }
+    admission();
+    periodic();
+    budget();
+    println!("sched_deadline passed!");
+    0
+}
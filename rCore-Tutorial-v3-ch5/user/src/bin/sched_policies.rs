@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{SchedPolicy, exit, fork, get_time, sched_setpolicy, set_priority, wait};
+
+const POLICIES: [SchedPolicy; 5] = [
+    SchedPolicy::Fifo,
+    SchedPolicy::RoundRobin,
+    SchedPolicy::Mlfq,
+    SchedPolicy::Cfs,
+    SchedPolicy::Stride,
+];
+const PRIORITIES: [isize; 3] = [2, 4, 8];
+const SPIN_MS: isize = 300;
+
+// 在同一时刻开始空转，统计固定时间内能执行多少轮，轮数与得到的CPU时间成正比
+fn spin(prio: isize, start: isize) -> ! {
+    assert_eq!(set_priority(prio), prio);
+    while get_time() < start {}
+    let mut rounds = 0;
+    while get_time() < start + SPIN_MS {
+        rounds += 1;
+    }
+    // 轮数可能超出退出码的范围
+    exit(rounds / 1000)
+}
+
+// 在当前策略下运行不同优先级的空转进程，返回它们各自的轮数（千轮）
+fn run_spinners() -> [i32; PRIORITIES.len()] {
+    let start = get_time() + 50;
+    let mut pids = [0; PRIORITIES.len()];
+    for (pid, &prio) in pids.iter_mut().zip(PRIORITIES.iter()) {
+        *pid = fork();
+        if *pid == 0 {
+            spin(prio, start);
+        }
+    }
+    let mut rounds = [0; PRIORITIES.len()];
+    for _ in 0..PRIORITIES.len() {
+        let mut exit_code: i32 = 0;
+        let pid = wait(&mut exit_code);
+        let index = pids.iter().position(|&p| p == pid).unwrap();
+        rounds[index] = exit_code;
+    }
+    rounds
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let original = match sched_setpolicy(SchedPolicy::Fifo, 0) {
+        Some(policy) => policy,
+        None => {
+            println!("sched_policies: deterministic scheduling, skipped");
+            return 0;
+        }
+    };
+    let mut current = SchedPolicy::Fifo;
+    for policy in POLICIES {
+        // 每次切换都返回上一次设置的策略
+        assert_eq!(sched_setpolicy(policy, 20_000), Some(current));
+        current = policy;
+        let rounds = run_spinners();
+        println!("{:?}: {:?} k rounds", policy, rounds);
+        // 每个进程都得到了CPU时间
+        assert!(rounds.iter().all(|&r| r > 0));
+        // 步长调度和完全公平调度都按优先级分配CPU时间
+        if policy == SchedPolicy::Cfs || policy == SchedPolicy::Stride {
+            assert!(rounds[1] * 2 > rounds[0] * 3);
+            assert!(rounds[2] * 2 > rounds[1] * 3);
+        }
+    }
+    assert_eq!(sched_setpolicy(original, 0), Some(current));
+    println!("sched_policies passed!");
+    0
+}
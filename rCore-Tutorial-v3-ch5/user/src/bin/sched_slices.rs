@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{SchedPolicy, coroutine_stats, exit, fork, get_time, sched_setpolicy, wait};
+
+// 主执行流的协程ID
+const MAIN_CID: usize = 0;
+const SPINNERS: usize = 2;
+const SPIN_MS: isize = 400;
+
+// 空转一段时间，以主执行流被抢占的次数退出
+fn spin(start: isize) -> ! {
+    // 统计从父进程复制而来，只计算新增的次数
+    let before = coroutine_stats(MAIN_CID).unwrap().involuntary_switches;
+    while get_time() < start {}
+    while get_time() < start + SPIN_MS {}
+    let after = coroutine_stats(MAIN_CID).unwrap().involuntary_switches;
+    exit((after - before) as i32)
+}
+
+// 在给定时间片的轮转调度下运行几个空转进程，返回它们平均被抢占的次数
+fn preemptions(quantum_us: usize) -> i32 {
+    sched_setpolicy(SchedPolicy::RoundRobin, quantum_us).unwrap();
+    let start = get_time() + 50;
+    for _ in 0..SPINNERS {
+        if fork() == 0 {
+            spin(start);
+        }
+    }
+    let mut total = 0;
+    for _ in 0..SPINNERS {
+        let mut exit_code: i32 = 0;
+        assert!(wait(&mut exit_code) > 0);
+        total += exit_code;
+    }
+    println!(
+        "quantum {} us: {} preemptions",
+        quantum_us,
+        total / SPINNERS as i32
+    );
+    total / SPINNERS as i32
+}
+
+// 时钟只在时间片用完时中断，时间片越长，被抢占的次数越少
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let original = match sched_setpolicy(SchedPolicy::RoundRobin, 0) {
+        Some(policy) => policy,
+        None => {
+            println!("sched_slices: deterministic scheduling, skipped");
+            return 0;
+        }
+    };
+    let short = preemptions(10_000);
+    let long = preemptions(100_000);
+    sched_setpolicy(original, 0);
+    assert!(short > 0);
+    assert!(long * 3 < short);
+    println!("sched_slices passed!");
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_priority, wait};
+
+const PRIORITIES: [isize; 3] = [2, 4, 8];
+const SPIN_MS: isize = 600;
+
+// 在同一时刻开始空转，统计固定时间内能执行多少轮，轮数与得到的CPU时间成正比
+fn spin(prio: isize, start: isize) -> ! {
+    assert_eq!(set_priority(prio), prio);
+    while get_time() < start {}
+    let mut rounds = 0;
+    while get_time() < start + SPIN_MS {
+        rounds += 1;
+    }
+    // 轮数可能超出退出码的范围
+    exit(rounds / 1000)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(set_priority(0), -1);
+
+    let start = get_time() + 50;
+    let mut pids = [0; PRIORITIES.len()];
+    for (pid, &prio) in pids.iter_mut().zip(PRIORITIES.iter()) {
+        *pid = fork();
+        if *pid == 0 {
+            spin(prio, start);
+        }
+    }
+    let mut rounds = [0; PRIORITIES.len()];
+    for _ in 0..PRIORITIES.len() {
+        let mut exit_code: i32 = 0;
+        let pid = wait(&mut exit_code);
+        let index = pids.iter().position(|&p| p == pid).unwrap();
+        rounds[index] = exit_code;
+    }
+    for (prio, rounds) in PRIORITIES.iter().zip(rounds) {
+        println!(
+            "priority {}: {} k rounds, {} per priority",
+            prio,
+            rounds,
+            rounds as isize / prio
+        );
+    }
+    // 优先级加倍，得到的CPU时间应当明显更多（理想情况下也加倍）
+    assert!(rounds[1] * 2 > rounds[0] * 3);
+    assert!(rounds[2] * 2 > rounds[1] * 3);
+    println!("stride_priority passed!");
+    0
+}
@@ -14,7 +14,24 @@ const BS: u8 = 0x08u8;
 
 use alloc::string::String;
 use user_lib::console::getchar;
-use user_lib::{exec, fork, waitpid};
+use user_lib::{TaskTimes, exec, fork, times, waitpid};
+
+// 打印一条命令占用的CPU时间和上下文切换次数，即前后两次回收的子进程合计之差
+fn report_times(before: &TaskTimes, after: &TaskTimes) {
+    let user_us = after.children_user_us - before.children_user_us;
+    let kernel_us = after.children_kernel_us - before.children_kernel_us;
+    let voluntary = after.children_voluntary_switches - before.children_voluntary_switches;
+    let involuntary = after.children_involuntary_switches - before.children_involuntary_switches;
+    println!(
+        "Shell: user {}.{:03}ms, sys {}.{:03}ms, {} voluntary / {} involuntary switches",
+        user_us / 1000,
+        user_us % 1000,
+        kernel_us / 1000,
+        kernel_us % 1000,
+        voluntary,
+        involuntary
+    );
+}
 
 #[unsafe(no_mangle)]
 pub fn main() -> i32 {
@@ -28,6 +45,7 @@ pub fn main() -> i32 {
                 println!("");
                 if !line.is_empty() {
                     line.push('\0');
+                    let before = times();
                     let pid = fork();
                     if pid == 0 {
                         // child process
@@ -41,6 +59,7 @@ pub fn main() -> i32 {
                         let exit_pid = waitpid(pid as usize, &mut exit_code);
                         assert_eq!(pid, exit_pid);
                         println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        report_times(&before, &times());
                     }
                     line.clear();
                 }
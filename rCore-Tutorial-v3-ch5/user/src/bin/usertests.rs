@@ -5,7 +5,8 @@
 extern crate user_lib;
 
 // not in SUCC_TESTS & FAIL_TESTS
-// count_lines, infloop, user_shell, usertests
+// bench_coroutine, bench_process, count_lines, coroutine_trace, infloop, sched_compare, user_shell,
+// usertests
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
@@ -20,6 +21,27 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
+    ("coroutine_waitpid\0", "\0", "\0", "\0", 0),
+    ("coroutine_info\0", "\0", "\0", "\0", 0),
+    ("coroutine_stack\0", "\0", "\0", "\0", 0),
+    ("coroutine_many\0", "\0", "\0", "\0", 0),
+    ("coroutine_fault\0", "\0", "\0", "\0", 0),
+    ("coroutine_fork\0", "\0", "\0", "\0", 0),
+    ("coroutine_exit_policy\0", "\0", "\0", "\0", 0),
+    ("coroutine_pool\0", "\0", "\0", "\0", 0),
+    ("coroutine_stats\0", "\0", "\0", "\0", 0),
+    ("coroutine_deadlock\0", "\0", "\0", "\0", 0),
+    ("coroutine_notify\0", "\0", "\0", "\0", 0),
+    ("coroutine_fast_yield\0", "\0", "\0", "\0", 0),
+    ("coroutine_upcall\0", "\0", "\0", "\0", 0),
+    ("coroutine_trace_events\0", "\0", "\0", "\0", 0),
+    ("fp_context\0", "\0", "\0", "\0", 0),
+    ("coroutine_generator\0", "\0", "\0", "\0", 0),
+    ("stride_priority\0", "\0", "\0", "\0", 0),
+    ("sched_policies\0", "\0", "\0", "\0", 0),
+    ("sched_deadline\0", "\0", "\0", "\0", 0),
+    ("sched_slices\0", "\0", "\0", "\0", 0),
+    ("cpu_times\0", "\0", "\0", "\0", 0),
 ];
 
 static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[("stack_overflow\0", "\0", "\0", "\0", -2)];
@@ -1,11 +1,39 @@
 // user/src/coroutine.rs
-use crate::syscall::{syscall};
+use crate::syscall::{syscall, sys_fork, sys_waitpid};
+use alloc::boxed::Box;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 // 系统调用号
 const SYSCALL_COROUTINE_CREATE: usize = 600;
 const SYSCALL_COROUTINE_YIELD: usize = 601;
 const SYSCALL_COROUTINE_RESUME: usize = 602;
 const SYSCALL_COROUTINE_EXIT: usize = 603;
+const SYSCALL_COROUTINE_WAITPID: usize = 604;
+const SYSCALL_COROUTINE_INFO: usize = 605;
+const SYSCALL_COROUTINE_STACK_USAGE: usize = 606;
+const SYSCALL_COROUTINE_FAULT_POLICY: usize = 607;
+const SYSCALL_COROUTINE_EXIT_POLICY: usize = 608;
+const SYSCALL_COROUTINE_DETACH: usize = 609;
+const SYSCALL_COROUTINE_STACK_CACHE_LIMIT: usize = 610;
+const SYSCALL_COROUTINE_TRIM_STACK_CACHE: usize = 611;
+const SYSCALL_COROUTINE_SET_NAME: usize = 612;
+const SYSCALL_COROUTINE_STATS: usize = 613;
+const SYSCALL_COROUTINE_DEADLOCK_POLICY: usize = 614;
+const SYSCALL_COROUTINE_SUSPEND: usize = 615;
+const SYSCALL_COROUTINE_NOTIFY: usize = 616;
+const SYSCALL_COROUTINE_WAIT_NOTIFY: usize = 617;
+const SYSCALL_COROUTINE_FAST_PATH: usize = 618;
+const SYSCALL_COROUTINE_UPCALL: usize = 619;
+const SYSCALL_COROUTINE_UPCALL_RETURN: usize = 620;
+const SYSCALL_COROUTINE_TRACE: usize = 621;
+const SYSCALL_TRACE_READ: usize = 622;
+
+// 与内核共享的协程调度页的地址，紧挨在陷入上下文页下面
+const COROUTINE_SCHED_PAGE: usize = usize::MAX - 3 * 4096 + 1;
+
+// 协程名字占用的字节数，与内核一致，名字最长为这个长度减一
+pub const COROUTINE_NAME_LEN: usize = 16;
 
 // 协程ID类型
 pub type CoroutineId = usize;
@@ -13,17 +41,344 @@ pub type CoroutineId = usize;
 // 协程函数类型
 pub type CoroutineFunc = fn(usize) -> i32;
 
+// 协程状态，与内核中 CoroutineStatus 的编号一致
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CoroutineStatus {
+    Ready,
+    Running,
+    Blocked,
+    Exited,
+    Unknown,
+}
+
+impl From<usize> for CoroutineStatus {
+    fn from(status: usize) -> Self {
+        match status {
+            0 => CoroutineStatus::Ready,
+            1 => CoroutineStatus::Running,
+            2 => CoroutineStatus::Blocked,
+            3 => CoroutineStatus::Exited,
+            _ => CoroutineStatus::Unknown,
+        }
+    }
+}
+
+// sys_coroutine_info 为每个协程填写的信息
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CoroutineInfo {
+    pub cid: usize,
+    pub status: usize,
+    pub entry: usize,
+    pub stack_base: usize,
+    pub stack_size: usize,
+    pub switch_count: usize,
+    pub run_time_us: usize,
+    pub exit_code: isize,
+    pub name: [u8; COROUTINE_NAME_LEN],
+}
+
+// sys_coroutine_stats 填写的调度统计
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CoroutineStats {
+    pub cid: usize,
+    // 被调度运行的次数
+    pub schedule_count: usize,
+    // 主动让出、恢复其他协程、阻塞或退出的次数
+    pub voluntary_switches: usize,
+    // 运行时进程被时钟中断抢占的次数
+    pub involuntary_switches: usize,
+    // 占用CPU的累计时间（微秒）
+    pub run_time_us: usize,
+    // 阻塞的累计时间（微秒）
+    pub blocked_time_us: usize,
+}
+
+impl CoroutineStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoroutineStatus::Ready => "Ready",
+            CoroutineStatus::Running => "Running",
+            CoroutineStatus::Blocked => "Blocked",
+            CoroutineStatus::Exited => "Exited",
+            CoroutineStatus::Unknown => "Unknown",
+        }
+    }
+}
+
+impl CoroutineInfo {
+    pub fn status(&self) -> CoroutineStatus {
+        CoroutineStatus::from(self.status)
+    }
+
+    // 协程的名字，没有名字时为空串
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|byte| *byte == 0).unwrap_or(COROUTINE_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+// 协程中发生缺页、非法指令等致命异常时的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultPolicy {
+    // 终止整个进程（默认）
+    KillProcess = 0,
+    // 只终止出错的协程，退出码与进程相同：缺页为 -2，非法指令为 -3
+    KillCoroutine = 1,
+}
+
+// 死锁时被终止的进程的退出码，也是 DeadlockPolicy::Error 下主执行流的阻塞调用得到的错误码
+pub const DEADLOCK_EXIT_CODE: i32 = -4;
+
+// 进程内所有协程都阻塞、且没有任何事件能唤醒它们时的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DeadlockPolicy {
+    // 内核报告阻塞的协程以及它们等待的事件，然后继续等待（默认）
+    Report = 0,
+    // 报告后唤醒主执行流，它的阻塞调用返回 DEADLOCK_EXIT_CODE
+    Error = 1,
+    // 报告后以 DEADLOCK_EXIT_CODE 终止进程
+    Kill = 2,
+}
+
+// 主执行流退出进程（包括 main 返回）时对仍未退出的协程的处理策略
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExitPolicy {
+    // 直接终止所有协程（默认）
+    KillAll = 0,
+    // 等待所有未分离的协程退出后再退出进程，分离的协程随进程一起终止
+    WaitAll = 1,
+}
+
+// fork时子进程继承协程的方式
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ForkMode {
+    // 复制所有协程，包括它们的栈和保存的寄存器（fork 的默认行为）
+    AllCoroutines = 0,
+    // 只保留调用fork的协程，它在子进程中成为主执行流
+    CurrentCoroutine = 1,
+}
+
 // 定义默认栈大小
 const DEFAULT_STACK_SIZE: usize = 8192; // 8KB
 
+// 协程启动时需要的信息，由 coroutine_entry 取出并释放
+struct CoroutineStart {
+    func: CoroutineFunc,
+    arg: usize,
+}
+
+// 所有协程的真正入口：执行用户函数，并以它的返回值退出协程
+// 启动信息在调用用户函数前就释放，协程因异常被内核终止时也不会泄漏
+extern "C" fn coroutine_entry(start: usize) -> ! {
+    let CoroutineStart { func, arg } = *unsafe { Box::from_raw(start as *mut CoroutineStart) };
+    coroutine_exit(func(arg))
+}
+
 // 协程创建包装函数
 pub fn coroutine_create(func: CoroutineFunc, arg: usize) -> CoroutineId {
-    let func_addr = func as usize;
-    syscall(SYSCALL_COROUTINE_CREATE, [func_addr, arg, DEFAULT_STACK_SIZE]) as CoroutineId
+    coroutine_create_with_stack(func, arg, DEFAULT_STACK_SIZE)
+}
+
+// 以指定的栈大小创建协程，栈大小必须是页大小（4096）的正整数倍且不超过1MiB，否则返回-1
+pub fn coroutine_create_with_stack(func: CoroutineFunc, arg: usize, stack_size: usize) -> CoroutineId {
+    let start = Box::into_raw(Box::new(CoroutineStart { func, arg }));
+    let cid = syscall(
+        SYSCALL_COROUTINE_CREATE,
+        [coroutine_entry as usize, start as usize, stack_size],
+    );
+    if cid < 0 {
+        drop(unsafe { Box::from_raw(start) });
+    }
+    cid as CoroutineId
+}
+
+// 协程在用户态切换出去时保存的寄存器，只需保存被调用者保存的寄存器，包括浮点寄存器 fs0-fs11
+#[repr(C)]
+#[derive(Default)]
+struct UserContext {
+    ra: usize,
+    sp: usize,
+    s: [usize; 12],
+    fs: [usize; 12],
+    // 为1时保存了浮点寄存器和 fcsr，进程的浮点单元还没有打开时不保存
+    fp_saved: usize,
+    // 舍入模式和累积的异常标志属于各个协程
+    fcsr: usize,
+}
+
+// 调度页中 fp_enabled 的偏移，它为1时进程的浮点单元已经打开
+const SCHED_FP_ENABLED: usize = 24 + 16 * 128;
+
+// 在调度页中切换协程，页的布局与内核中的 CoroutineSchedPage 一致：
+// current_head((当前协程ID << 8) | 队首下标) @0, ready_len @8, valid @16,
+// ready[128] @24，每项为 (cid, user_cx) 共16字节，fp_enabled @2072。
+// 从 begin 到写入 current_head 为止被陷入打断时，内核让它从 begin 重新执行，
+// 因此这一段只能读取 a0、a1，且在最后一条写指令之前不能改变页中生效的状态。
+// 队首协程的寄存器由内核保存时改用系统调用切换，同时把已保存的寄存器交给内核，
+// 内核之后同样经 __coroutine_user_restore 恢复当前协程，使它以后可以在用户态被切换过去
+global_asm!(
+    r#"
+    .option push
+    .option arch, +d
+    .section .text
+    .globl __coroutine_yield
+    .globl __coroutine_fast_yield_begin
+    .globl __coroutine_fast_yield_end
+    .globl __coroutine_user_restore
+    .align 2
+# a0: 调度页, a1: 保存当前协程寄存器的 UserContext
+# 切换出去又被切换回来时返回 0，没有就绪的协程时返回 -1
+__coroutine_yield:
+    sd ra, 0(a1)
+    sd sp, 8(a1)
+    sd s0, 16(a1)
+    sd s1, 24(a1)
+    sd s2, 32(a1)
+    sd s3, 40(a1)
+    sd s4, 48(a1)
+    sd s5, 56(a1)
+    sd s6, 64(a1)
+    sd s7, 72(a1)
+    sd s8, 80(a1)
+    sd s9, 88(a1)
+    sd s10, 96(a1)
+    sd s11, 104(a1)
+    # 浮点单元还没有打开时进程没有浮点状态，不碰浮点寄存器，以免无谓地打开它
+    li t0, {fp_enabled}
+    add t0, t0, a0
+    ld t0, 0(t0)
+    sd t0, 208(a1)
+    beqz t0, 4f
+    fsd fs0, 112(a1)
+    fsd fs1, 120(a1)
+    fsd fs2, 128(a1)
+    fsd fs3, 136(a1)
+    fsd fs4, 144(a1)
+    fsd fs5, 152(a1)
+    fsd fs6, 160(a1)
+    fsd fs7, 168(a1)
+    fsd fs8, 176(a1)
+    fsd fs9, 184(a1)
+    fsd fs10, 192(a1)
+    fsd fs11, 200(a1)
+    frcsr t0
+    sd t0, 216(a1)
+4:
+__coroutine_fast_yield_begin:
+    ld t0, 16(a0)
+    beqz t0, 3f
+    ld t1, 8(a0)
+    beqz t1, 3f
+    ld t2, 0(a0)
+    andi t3, t2, 0xff
+    srli t2, t2, 8
+    # 队首的协程必须是在用户态切换出去的
+    slli t4, t3, 4
+    add t4, t4, a0
+    ld t5, 32(t4)
+    beqz t5, 3f
+    ld t6, 24(t4)
+    # 当前协程放到队尾，队尾之后的位置不属于就绪环，写入不影响生效的状态
+    add t4, t3, t1
+    li a2, 128
+    bltu t4, a2, 1f
+    sub t4, t4, a2
+1:
+    slli t4, t4, 4
+    add t4, t4, a0
+    sd t2, 24(t4)
+    sd a1, 32(t4)
+    addi t3, t3, 1
+    bltu t3, a2, 2f
+    li t3, 0
+2:
+    slli t6, t6, 8
+    or t6, t6, t3
+    sd t6, 0(a0)
+__coroutine_fast_yield_end:
+    mv a0, t5
+    j __coroutine_user_restore
+3:
+    mv a0, a1
+    li a7, {yield_id}
+    ecall
+    ret
+
+# a0: 要切换到的协程保存寄存器的 UserContext，内核切换到在用户态切换出去的协程时也从这里进入
+__coroutine_user_restore:
+    ld ra, 0(a0)
+    ld sp, 8(a0)
+    ld s0, 16(a0)
+    ld s1, 24(a0)
+    ld s2, 32(a0)
+    ld s3, 40(a0)
+    ld s4, 48(a0)
+    ld s5, 56(a0)
+    ld s6, 64(a0)
+    ld s7, 72(a0)
+    ld s8, 80(a0)
+    ld s9, 88(a0)
+    ld s10, 96(a0)
+    ld s11, 104(a0)
+    ld t0, 208(a0)
+    beqz t0, 5f
+    fld fs0, 112(a0)
+    fld fs1, 120(a0)
+    fld fs2, 128(a0)
+    fld fs3, 136(a0)
+    fld fs4, 144(a0)
+    fld fs5, 152(a0)
+    fld fs6, 160(a0)
+    fld fs7, 168(a0)
+    fld fs8, 176(a0)
+    fld fs9, 184(a0)
+    fld fs10, 192(a0)
+    fld fs11, 200(a0)
+    ld t0, 216(a0)
+    fscsr t0
+5:
+    li a0, 0
+    ret
+    .option pop
+"#,
+    yield_id = const SYSCALL_COROUTINE_YIELD,
+    fp_enabled = const SCHED_FP_ENABLED,
+);
+
+unsafe extern "C" {
+    fn __coroutine_yield(page: usize, cx: *mut UserContext) -> isize;
+    fn __coroutine_fast_yield_begin();
+    fn __coroutine_fast_yield_end();
+    fn __coroutine_user_restore();
 }
 
-// 协程主动让出CPU
+// 0: 尚未向内核登记快速切换，1: 已登记，2: 内核不支持
+static FAST_PATH: AtomicUsize = AtomicUsize::new(0);
+
+// 第一次让出时向内核登记快速切换的代码，fork 出的子进程随地址空间一起继承
+fn fast_path_enabled() -> bool {
+    if FAST_PATH.load(Ordering::Relaxed) == 0 {
+        let ret = syscall(
+            SYSCALL_COROUTINE_FAST_PATH,
+            [
+                __coroutine_user_restore as usize,
+                __coroutine_fast_yield_begin as usize,
+                __coroutine_fast_yield_end as usize,
+            ],
+        );
+        FAST_PATH.store(if ret == 0 { 1 } else { 2 }, Ordering::Relaxed);
+    }
+    FAST_PATH.load(Ordering::Relaxed) == 1
+}
+
+// 协程主动让出CPU；下一个就绪协程也是在用户态让出的时候，直接在用户态切换过去，不陷入内核
 pub fn coroutine_yield() -> isize {
+    if fast_path_enabled() {
+        let mut cx = UserContext::default();
+        return unsafe { __coroutine_yield(COROUTINE_SCHED_PAGE, &mut cx) };
+    }
     syscall(SYSCALL_COROUTINE_YIELD, [0, 0, 0])
 }
 
@@ -32,8 +387,256 @@ pub fn coroutine_resume(cid: CoroutineId) -> isize {
     syscall(SYSCALL_COROUTINE_RESUME, [cid, 0, 0])
 }
 
-// 退出当前协程
+// 退出当前协程；主执行流不能以协程的方式退出，此时退出整个进程，
+// 例如以 ForkMode::CurrentCoroutine fork 出的子进程中协程函数返回时
 pub fn coroutine_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_COROUTINE_EXIT, [exit_code as usize, 0, 0]);
-    panic!("coroutine exit failed");
-}
\ No newline at end of file
+    crate::exit(exit_code)
+}
+
+// 等待子进程退出，只挂起当前协程，进程内其他协程照常运行
+pub fn coroutine_waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(pid as isize, exit_code as *mut _) {
+            -2 => {
+                syscall(SYSCALL_COROUTINE_WAITPID, [pid, 0, 0]);
+            }
+            // -1 or a real pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
+
+// 查询进程 pid（-1 表示当前进程，也可以是任何存活的进程）的协程信息，
+// 返回该进程的协程总数，找不到进程时返回 -1
+// 未分离的已退出协程只保留最近退出的 32 个，更早退出的不再出现
+pub fn coroutine_info(pid: isize, infos: &mut [CoroutineInfo]) -> isize {
+    syscall(
+        SYSCALL_COROUTINE_INFO,
+        [pid as usize, infos.as_mut_ptr() as usize, infos.len()],
+    )
+}
+
+// 查询协程栈的最大使用量（字节），协程不存在或没有独立的栈时返回 -1
+pub fn coroutine_stack_usage(cid: CoroutineId) -> isize {
+    syscall(SYSCALL_COROUTINE_STACK_USAGE, [cid, 0, 0])
+}
+
+// 设置协程致命异常的处理策略，返回原来的策略
+pub fn coroutine_set_fault_policy(policy: FaultPolicy) -> FaultPolicy {
+    match syscall(SYSCALL_COROUTINE_FAULT_POLICY, [policy as usize, 0, 0]) {
+        1 => FaultPolicy::KillCoroutine,
+        _ => FaultPolicy::KillProcess,
+    }
+}
+
+// 以指定的方式继承协程并创建子进程，子进程中返回 0
+pub fn coroutine_fork(mode: ForkMode) -> isize {
+    sys_fork(mode as usize)
+}
+
+// 设置主执行流退出进程时的处理策略，返回原来的策略；其他协程调用 exit 总是立即退出进程
+pub fn coroutine_set_exit_policy(policy: ExitPolicy) -> ExitPolicy {
+    match syscall(SYSCALL_COROUTINE_EXIT_POLICY, [policy as usize, 0, 0]) {
+        1 => ExitPolicy::WaitAll,
+        _ => ExitPolicy::KillAll,
+    }
+}
+
+// 分离协程：它不会推迟进程退出，退出后立即被回收，不再出现在 coroutine_info 中
+pub fn coroutine_detach(cid: CoroutineId) -> isize {
+    syscall(SYSCALL_COROUTINE_DETACH, [cid, 0, 0])
+}
+
+// 设置最多缓存多少个已退出协程的栈供新协程复用（默认 8 个），返回原来的上限
+pub fn coroutine_set_stack_cache_limit(limit: usize) -> usize {
+    syscall(SYSCALL_COROUTINE_STACK_CACHE_LIMIT, [limit, 0, 0]) as usize
+}
+
+// 释放缓存的协程栈，最多保留 keep 个，返回释放的栈数量
+pub fn coroutine_trim_stack_cache(keep: usize) -> usize {
+    syscall(SYSCALL_COROUTINE_TRIM_STACK_CACHE, [keep, 0, 0]) as usize
+}
+
+// 为协程设置用于诊断的名字，超过 COROUTINE_NAME_LEN - 1 字节的部分被截断
+pub fn coroutine_set_name(cid: CoroutineId, name: &str) -> isize {
+    let mut buffer = [0u8; COROUTINE_NAME_LEN];
+    let len = name.len().min(COROUTINE_NAME_LEN - 1);
+    buffer[..len].copy_from_slice(&name.as_bytes()[..len]);
+    syscall(SYSCALL_COROUTINE_SET_NAME, [cid, buffer.as_ptr() as usize, 0])
+}
+
+// 查询当前进程中协程的调度统计，协程不存在时返回 None
+pub fn coroutine_stats(cid: CoroutineId) -> Option<CoroutineStats> {
+    let mut stats = CoroutineStats::default();
+    match syscall(SYSCALL_COROUTINE_STATS, [cid, &mut stats as *mut _ as usize, 0]) {
+        0 => Some(stats),
+        _ => None,
+    }
+}
+
+// 设置所有协程死锁时的处理策略，返回原来的策略
+pub fn coroutine_set_deadlock_policy(policy: DeadlockPolicy) -> DeadlockPolicy {
+    match syscall(SYSCALL_COROUTINE_DEADLOCK_POLICY, [policy as usize, 0, 0]) {
+        1 => DeadlockPolicy::Error,
+        2 => DeadlockPolicy::Kill,
+        _ => DeadlockPolicy::Report,
+    }
+}
+
+// 挂起当前协程，直到其他协程用 coroutine_resume 恢复它，此时返回 0
+pub fn coroutine_suspend() -> isize {
+    syscall(SYSCALL_COROUTINE_SUSPEND, [0, 0, 0])
+}
+
+// 唤醒进程 pid（自己、父进程或子进程）中等待通知的协程 cid，并交给它 value；
+// 协程没有在等待时通知被保存一个，下次等待时立即得到。
+// 唤醒了协程返回 0，通知被保存返回 1，找不到进程或协程返回 -1，已有未取走的通知返回 -2
+pub fn coroutine_notify(pid: usize, cid: CoroutineId, value: usize) -> isize {
+    syscall(SYSCALL_COROUTINE_NOTIFY, [pid, cid, value])
+}
+
+// 等待发给当前协程的通知，返回它携带的值
+// 进程没有子进程时，所有协程都在等待通知或被挂起会被当作死锁，按死锁处理策略处理
+pub fn coroutine_wait_notify() -> usize {
+    syscall(SYSCALL_COROUTINE_WAIT_NOTIFY, [0, 0, 0]) as usize
+}
+
+// 上行调用通知的事件，与内核中 UpcallEvent 的编号一致
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum UpcallEvent {
+    // 协程在内核中阻塞
+    Blocked = 0,
+    // 阻塞的协程被唤醒，重新就绪
+    Unblocked = 1,
+}
+
+// 上行调用处理函数，参数为事件和事件涉及的协程
+pub type UpcallHandler = fn(UpcallEvent, CoroutineId);
+
+static UPCALL_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// 内核每次都从这里开始把一个事件交给处理函数，处理完后由内核交给它下一个事件或切换到其他协程
+extern "C" fn upcall_entry(event: usize, cid: usize) -> ! {
+    let handler = UPCALL_HANDLER.load(Ordering::Relaxed);
+    let handler: UpcallHandler = unsafe { core::mem::transmute(handler) };
+    let event = match event {
+        0 => UpcallEvent::Blocked,
+        _ => UpcallEvent::Unblocked,
+    };
+    handler(event, cid);
+    syscall(SYSCALL_COROUTINE_UPCALL_RETURN, [0, 0, 0]);
+    unreachable!("upcall handler returned outside of an upcall");
+}
+
+// 登记上行调用处理函数：协程在内核中阻塞或被唤醒时，内核在一个专门的协程上调用它，
+// 用户态运行时可以借此在协程阻塞期间安排其他工作。处理函数可以恢复其他协程，但自己不能阻塞。
+// 返回运行处理函数的协程ID
+pub fn coroutine_set_upcall(handler: UpcallHandler) -> isize {
+    UPCALL_HANDLER.store(handler as usize, Ordering::Relaxed);
+    syscall(
+        SYSCALL_COROUTINE_UPCALL,
+        [upcall_entry as usize, DEFAULT_STACK_SIZE, 0],
+    )
+}
+
+// 暂停上行调用并丢弃还没有处理的事件，返回运行处理函数的协程ID，没有登记过时返回 -1
+pub fn coroutine_clear_upcall() -> isize {
+    syscall(SYSCALL_COROUTINE_UPCALL, [0, 0, 0])
+}
+
+// 协程跟踪事件的类型，与内核中 TraceKind 的编号一致
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TraceKind {
+    // 协程被创建，参数是创建它的协程ID
+    Create,
+    // 协程成为当前协程
+    SwitchIn,
+    // 协程不再是当前协程
+    SwitchOut,
+    Block,
+    Unblock,
+    // 协程退出，参数是退出码
+    Exit,
+    // 内核跟踪缓冲区满了以后丢弃了最早的记录，参数是丢弃的条数
+    Lost,
+    Unknown,
+}
+
+impl From<usize> for TraceKind {
+    fn from(kind: usize) -> Self {
+        match kind {
+            0 => TraceKind::Create,
+            1 => TraceKind::SwitchIn,
+            2 => TraceKind::SwitchOut,
+            3 => TraceKind::Block,
+            4 => TraceKind::Unblock,
+            5 => TraceKind::Exit,
+            6 => TraceKind::Lost,
+            _ => TraceKind::Unknown,
+        }
+    }
+}
+
+impl TraceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TraceKind::Create => "create",
+            TraceKind::SwitchIn => "switch_in",
+            TraceKind::SwitchOut => "switch_out",
+            TraceKind::Block => "block",
+            TraceKind::Unblock => "unblock",
+            TraceKind::Exit => "exit",
+            TraceKind::Lost => "lost",
+            TraceKind::Unknown => "unknown",
+        }
+    }
+}
+
+// sys_trace_read 返回的一条跟踪记录
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TraceRecord {
+    // 事件发生的时间（微秒）
+    pub time_us: usize,
+    pub pid: usize,
+    pub cid: usize,
+    pub kind: usize,
+    pub arg: usize,
+}
+
+impl TraceRecord {
+    pub fn kind(&self) -> TraceKind {
+        TraceKind::from(self.kind)
+    }
+}
+
+// 每条记录一行：TRACE <时间(微秒)> <pid> <协程ID> <事件> <参数>，
+// user/scripts/trace2json.py 把这些行转换成 Chrome 的 trace event JSON
+impl core::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "TRACE {} {} {} {} {}",
+            self.time_us,
+            self.pid,
+            self.cid,
+            self.kind().as_str(),
+            self.arg as isize
+        )
+    }
+}
+
+// 开启或关闭当前进程的协程跟踪，返回原来是否开启。exec 后跟踪仍然开启，fork 出的子进程不跟踪
+pub fn coroutine_trace(enable: bool) -> bool {
+    syscall(SYSCALL_COROUTINE_TRACE, [enable as usize, 0, 0]) == 1
+}
+
+// 从内核跟踪缓冲区取出最早的若干条记录（所有被跟踪进程的记录都在其中），返回取出的条数。
+// 进程的记录在它返回用户态时才进入缓冲区
+pub fn trace_read(records: &mut [TraceRecord]) -> usize {
+    syscall(
+        SYSCALL_TRACE_READ,
+        [records.as_mut_ptr() as usize, records.len(), 0],
+    ) as usize
+}
@@ -0,0 +1,136 @@
+// 基于协程的生成器：函数体在自己的协程里运行，每次调用 gen_yield 交出一个值后挂起，
+// 消费者把它当作迭代器使用，每次 next 恢复一次生成器协程并取走它交出的值。
+//
+// 函数体中除 gen_yield 外不应让出或阻塞当前协程，否则它再次运行时 gen_yield
+// 找不到自己所属的生成器。生成器嵌套使用（函数体中迭代另一个生成器）没有问题。
+
+use crate::coroutine::{
+    CoroutineId, coroutine_create_with_stack, coroutine_detach, coroutine_exit, coroutine_resume,
+    coroutine_suspend,
+};
+use alloc::boxed::Box;
+use core::any::TypeId;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+// 生成器协程默认的栈大小
+const GENERATOR_STACK_SIZE: usize = 8192;
+
+// 与值的类型无关的状态，gen_yield 通过它找到当前的生成器并检查值的类型
+#[repr(C)]
+struct Header {
+    type_id: TypeId,
+    // 函数体已经返回，或者生成器被提前丢弃后协程已经退出
+    done: bool,
+    // 迭代器被丢弃，生成器协程恢复后应当立即退出
+    cancelled: bool,
+}
+
+#[repr(C)]
+struct State<T> {
+    header: Header,
+    // 交出但还没被取走的值
+    value: Option<T>,
+    body: Option<Box<dyn FnOnce()>>,
+}
+
+// 正在运行的生成器，由 next 在恢复生成器协程前设置、恢复返回后还原
+static CURRENT: AtomicPtr<Header> = AtomicPtr::new(null_mut());
+
+// 生成器，函数体中调用 gen_yield(x) 交出的值依次成为迭代器的元素
+// 状态由生成器协程和消费者共同访问，因此只以裸指针持有，在 drop 时释放
+pub struct Generator<T: 'static> {
+    cid: CoroutineId,
+    state: *mut State<T>,
+}
+
+// 生成器协程的入口：先挂起，等第一次 next 时再运行函数体
+fn generator_entry<T: 'static>(arg: usize) -> i32 {
+    let state = arg as *mut State<T>;
+    coroutine_suspend();
+    if unsafe { (*state).header.cancelled } {
+        return 0;
+    }
+    let body = unsafe { (*state).body.take().unwrap() };
+    body();
+    unsafe { (*state).header.done = true };
+    0
+}
+
+impl<T: 'static> Generator<T> {
+    // 以默认的栈大小创建生成器，创建失败时返回None
+    pub fn new(body: impl FnOnce() + 'static) -> Option<Self> {
+        Self::with_stack(body, GENERATOR_STACK_SIZE)
+    }
+
+    // 以指定的栈大小创建生成器，栈大小的要求同 coroutine_create_with_stack，创建失败时返回None
+    pub fn with_stack(body: impl FnOnce() + 'static, stack_size: usize) -> Option<Self> {
+        let state = Box::into_raw(Box::new(State {
+            header: Header {
+                type_id: TypeId::of::<T>(),
+                done: false,
+                cancelled: false,
+            },
+            value: None,
+            body: Some(Box::new(body)),
+        }));
+        let cid = coroutine_create_with_stack(generator_entry::<T>, state as usize, stack_size);
+        if (cid as isize) < 0 {
+            drop(unsafe { Box::from_raw(state) });
+            return None;
+        }
+        // 生成器协程不推迟进程退出，退出后立即回收栈
+        coroutine_detach(cid);
+        // 让它运行到入口处的挂起，此后只有 next 和 drop 会恢复它
+        coroutine_resume(cid);
+        Some(Self { cid, state })
+    }
+
+    // 恢复生成器协程，直到它交出下一个值、函数体返回或者在取消后退出
+    fn resume(&mut self) {
+        let header = self.state as *mut Header;
+        let prev = CURRENT.swap(header, Ordering::Relaxed);
+        coroutine_resume(self.cid);
+        CURRENT.store(prev, Ordering::Relaxed);
+    }
+}
+
+impl<T: 'static> Iterator for Generator<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if unsafe { (*self.state).header.done } {
+            return None;
+        }
+        self.resume();
+        unsafe { (*self.state).value.take() }
+    }
+}
+
+impl<T: 'static> Drop for Generator<T> {
+    // 没有迭代完时让生成器协程退出，释放它的栈；
+    // 函数体中还没执行完的局部变量不会被析构，它们占用的堆内存会泄漏
+    fn drop(&mut self) {
+        if !unsafe { (*self.state).header.done } {
+            unsafe { (*self.state).header.cancelled = true };
+            self.resume();
+        }
+        drop(unsafe { Box::from_raw(self.state) });
+    }
+}
+
+// 在生成器的函数体中交出一个值，挂起到下一次 next；值的类型必须与生成器一致
+pub fn gen_yield<T: 'static>(value: T) {
+    let header = CURRENT.load(Ordering::Relaxed);
+    assert!(
+        !header.is_null() && unsafe { (*header).type_id } == TypeId::of::<T>(),
+        "gen_yield called outside a generator of this type"
+    );
+    let state = header as *mut State<T>;
+    unsafe { (*state).value = Some(value) };
+    coroutine_suspend();
+    if unsafe { (*state).header.cancelled } {
+        unsafe { (*state).header.done = true };
+        coroutine_exit(0);
+    }
+}
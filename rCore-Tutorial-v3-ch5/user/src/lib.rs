@@ -2,19 +2,31 @@
 #![feature(linkage)]
 #![feature(alloc_error_handler)]
 
+extern crate alloc;
+
 #[macro_use]
 pub mod console;
+pub mod bench;
 mod lang_items;
 mod syscall;
 mod coroutine;
+mod generator;
 
 use buddy_system_allocator::LockedHeap;
 use core::ptr::addr_of_mut;
 use syscall::*;
 pub use coroutine::{
-    coroutine_create, coroutine_yield, coroutine_resume, coroutine_exit,
-    CoroutineId, CoroutineFunc,
+    coroutine_create, coroutine_create_with_stack, coroutine_yield, coroutine_resume,
+    coroutine_exit, coroutine_waitpid, coroutine_info, coroutine_stack_usage,
+    coroutine_set_fault_policy, coroutine_fork,
+    coroutine_set_exit_policy, coroutine_detach, coroutine_set_stack_cache_limit,
+    coroutine_trim_stack_cache, coroutine_set_name,
+    coroutine_stats, coroutine_set_deadlock_policy, coroutine_suspend, coroutine_notify,
+    coroutine_wait_notify, coroutine_set_upcall, coroutine_clear_upcall, coroutine_trace,
+    trace_read, CoroutineId, CoroutineStats, TraceKind, TraceRecord, UpcallEvent, UpcallHandler,
+    DeadlockPolicy, DEADLOCK_EXIT_CODE, COROUTINE_NAME_LEN, ExitPolicy, FaultPolicy, ForkMode, CoroutineFunc, CoroutineInfo, CoroutineStatus,
 };
+pub use generator::{gen_yield, Generator};
 const USER_HEAP_SIZE: usize = 16384;
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
@@ -55,14 +67,79 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn yield_() -> isize {
     sys_yield()
 }
+// 设置当前进程的优先级（至少为2），进程得到的CPU时间与优先级成正比
+pub fn set_priority(prio: isize) -> isize {
+    sys_set_priority(prio)
+}
+// 进程调度策略，编号与内核一致
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SchedPolicy {
+    // 先来先服务，每个时钟中断都抢占
+    Fifo = 0,
+    // 时间片轮转，时间片长度可以设置
+    RoundRobin = 1,
+    // 多级反馈队列
+    Mlfq = 2,
+    // 按虚拟运行时间的完全公平调度
+    Cfs = 3,
+    // 步长调度，CPU时间与优先级成正比（默认）
+    Stride = 4,
+}
+// 切换进程调度策略，quantum_us 是时间片轮转的时间片长度（微秒），0 表示默认值；
+// 返回原来的策略，确定性调度（SCHED_SEED）下不能切换，返回None
+pub fn sched_setpolicy(policy: SchedPolicy, quantum_us: usize) -> Option<SchedPolicy> {
+    match sys_sched_setpolicy(policy as usize, quantum_us) {
+        0 => Some(SchedPolicy::Fifo),
+        1 => Some(SchedPolicy::RoundRobin),
+        2 => Some(SchedPolicy::Mlfq),
+        3 => Some(SchedPolicy::Cfs),
+        4 => Some(SchedPolicy::Stride),
+        _ => None,
+    }
+}
+// 成为实时进程：每 period_us 微秒需要 runtime_us 微秒的CPU时间，并在周期开始后
+// deadline_us 微秒内完成，按最早截止时间优先调度，先于所有普通进程运行；
+// 调用 yield_ 表示本周期的工作已完成，等到下一个周期再运行；runtime_us 为0时恢复为普通进程。
+// 返回0；参数不满足 runtime <= deadline <= period <= 60秒 或者确定性调度时返回-1，
+// 实时进程的总利用率超出上限时返回-2
+pub fn sched_set_deadline(runtime_us: usize, period_us: usize, deadline_us: usize) -> isize {
+    sys_sched_set_deadline(runtime_us, period_us, deadline_us)
+}
+// 进程占用的CPU时间（微秒）和上下文切换次数
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TaskTimes {
+    // 在用户态运行的时间
+    pub user_us: usize,
+    // 内核为它运行的时间
+    pub kernel_us: usize,
+    // 主动让出CPU的次数
+    pub voluntary_switches: usize,
+    // 被抢占的次数
+    pub involuntary_switches: usize,
+    // 以下是所有已被等待回收的子进程（包括它们的子进程）的合计
+    pub children_user_us: usize,
+    pub children_kernel_us: usize,
+    pub children_voluntary_switches: usize,
+    pub children_involuntary_switches: usize,
+}
+// 当前进程以及已回收的子进程占用的CPU时间和上下文切换次数
+pub fn times() -> TaskTimes {
+    let mut times = TaskTimes::default();
+    sys_times(&mut times as *mut _);
+    times
+}
 pub fn get_time() -> isize {
     sys_get_time()
 }
+pub fn get_time_us() -> isize {
+    sys_get_time_us()
+}
 pub fn getpid() -> isize {
     sys_getpid()
 }
 pub fn fork() -> isize {
-    sys_fork()
+    sys_fork(ForkMode::AllCoroutines as usize)
 }
 pub fn exec(path: &str) -> isize {
     sys_exec(path)
@@ -1,14 +1,20 @@
+use crate::TaskTimes;
 use core::arch::asm;
 
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SCHED_SETPOLICY: usize = 119;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_TIMES: usize = 153;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GET_TIME_US: usize = 170;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_SCHED_SET_DEADLINE: usize = 274;
 
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -44,16 +50,36 @@ pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+pub fn sys_sched_setpolicy(policy: usize, quantum_us: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETPOLICY, [policy, quantum_us, 0])
+}
+
+pub fn sys_sched_set_deadline(runtime_us: usize, period_us: usize, deadline_us: usize) -> isize {
+    syscall(SYSCALL_SCHED_SET_DEADLINE, [runtime_us, period_us, deadline_us])
+}
+
+pub fn sys_set_priority(prio: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
+}
+
+pub fn sys_times(times: *mut TaskTimes) -> isize {
+    syscall(SYSCALL_TIMES, [times as usize, 0, 0])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
 
+pub fn sys_get_time_us() -> isize {
+    syscall(SYSCALL_GET_TIME_US, [0, 0, 0])
+}
+
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
-pub fn sys_fork() -> isize {
-    syscall(SYSCALL_FORK, [0, 0, 0])
+pub fn sys_fork(mode: usize) -> isize {
+    syscall(SYSCALL_FORK, [mode, 0, 0])
 }
 
 pub fn sys_exec(path: &str) -> isize {